@@ -12,12 +12,19 @@ use tokio::sync::broadcast;
 use tokio::time::sleep;
 use uuid::Uuid;
 
-use crate::interfaces::bluetooth::ControllerState;
+use crate::interfaces::{
+    bluetooth::{
+        ControllerState, GAMEPAD_REPORT_ID, KEYBOARD_REPORT_ID, MOUSE_REPORT_ID, REPORT_INTERVAL,
+        TurboClock,
+    },
+    internal::{Keyboard, MouseButtons},
+};
 
 // Correct UUIDs (16-bit UUIDs in proper 128-bit format)
 const DUALSHOCK_SERVICE_UUID: Uuid = bluetooth_uuid_from_u16(0x1812); // HID Service
 const HID_REPORT_UUID: Uuid = bluetooth_uuid_from_u16(0x2A4D); // Report
 const CCCD_UUID: Uuid = bluetooth_uuid_from_u16(0x2902); // Client Characteristic Config
+const REPORT_REFERENCE_UUID: Uuid = bluetooth_uuid_from_u16(0x2908); // Report Reference
 const REPORT_CHARACTERISTIC_UUID: Uuid = bluetooth_uuid_from_u16(0x2A4E); // Protocol Mode
 const REPORT_MAP_CHARACTERISTIC_UUID: Uuid = bluetooth_uuid_from_u16(0x2A4B);
 const HID_REPORT_MAP: &[u8] = &[
@@ -37,17 +44,7 @@ const HID_REPORT_MAP: &[u8] = &[
     0x75, 0x08, //   Report Size (8)
     0x95, 0x04, //   Report Count (4)
     0x81, 0x02, //   Input (Data,Var,Abs)
-    // Hat Switch + Face Buttons (1 byte)
-    0x05, 0x09, //   Usage Page (Button)
-    0x19, 0x01, //   Usage Minimum (Button 1)
-    0x29, 0x04, //   Usage Maximum (Button 4)
-    0x15, 0x00, //   Logical Minimum (0)
-    0x25, 0x01, //   Logical Maximum (1)
-    0x75, 0x01, //   Report Size (1)
-    0x95, 0x04, //   Report Count (4)
-    0x81, 0x02, //   Input (Data,Var,Abs)
-    // Hat Switch (4 bits)
-    0x05, 0x01, //   Usage Page (Generic Desktop)
+    // Hat Switch (lower 4 bits of byte 5)
     0x09, 0x39, //   Usage (Hat Switch)
     0x15, 0x00, //   Logical Minimum (0)
     0x25, 0x07, //   Logical Maximum (7)
@@ -58,29 +55,26 @@ const HID_REPORT_MAP: &[u8] = &[
     0x95, 0x01, //   Report Count (1)
     0x81, 0x42, //   Input (Data,Var,Abs,Null)
     0x65, 0x00, //   Unit (None)
-    // Buttons 5-8 (1 byte)
+    // Face Buttons (upper 4 bits of byte 5)
     0x05, 0x09, //   Usage Page (Button)
-    0x19, 0x05, //   Usage Minimum (Button 5)
-    0x29, 0x08, //   Usage Maximum (Button 8)
+    0x19, 0x01, //   Usage Minimum (Button 1)
+    0x29, 0x04, //   Usage Maximum (Button 4)
     0x15, 0x00, //   Logical Minimum (0)
     0x25, 0x01, //   Logical Maximum (1)
     0x75, 0x01, //   Report Size (1)
     0x95, 0x04, //   Report Count (4)
     0x81, 0x02, //   Input (Data,Var,Abs)
-    // Remaining buttons (byte 6)
-    0x19, 0x09, //   Usage Minimum (Button 9)
-    0x29, 0x10, //   Usage Maximum (Button 16)
+    // Shoulder + meta buttons (byte 6)
+    0x19, 0x05, //   Usage Minimum (Button 5)
+    0x29, 0x0C, //   Usage Maximum (Button 12)
     0x95, 0x08, //   Report Count (8)
     0x81, 0x02, //   Input (Data,Var,Abs)
-    // PS/Share/Touchpad buttons + Vendor (byte 7)
-    0x05, 0x0C, //   Usage Page (Consumer)
-    0x09, 0x01, //   Usage (Consumer Control)
-    0x15, 0x00, //   Logical Minimum (0)
-    0x25, 0x01, //   Logical Maximum (1)
-    0x75, 0x02, //   Report Size (2)
-    0x95, 0x01, //   Report Count (1)
+    // PS/Touchpad/Mute buttons + Vendor (byte 7)
+    0x19, 0x0D, //   Usage Minimum (Button 13)
+    0x29, 0x0F, //   Usage Maximum (Button 15)
+    0x95, 0x03, //   Report Count (3)
     0x81, 0x02, //   Input (Data,Var,Abs)
-    0x75, 0x06, //   Report Size (6)
+    0x75, 0x05, //   Report Size (5)
     0x95, 0x01, //   Report Count (1)
     0x81, 0x03, //   Input (Const,Var,Abs)
     // Trigger axes (2 bytes)
@@ -92,6 +86,84 @@ const HID_REPORT_MAP: &[u8] = &[
     0x75, 0x08, //   Report Size (8)
     0x95, 0x02, //   Report Count (2)
     0x81, 0x02, //   Input (Data,Var,Abs)
+    // Gyro + accelerometer (12 bytes)
+    0x05, 0x01, //   Usage Page (Generic Desktop)
+    0x09, 0x43, //   Usage (Vbrx)
+    0x09, 0x44, //   Usage (Vbry)
+    0x09, 0x45, //   Usage (Vbrz)
+    0x09, 0x40, //   Usage (Vx)
+    0x09, 0x41, //   Usage (Vy)
+    0x09, 0x42, //   Usage (Vz)
+    0x16, 0x00, 0x80, //   Logical Minimum (-32768)
+    0x26, 0xFF, 0x7F, //   Logical Maximum (32767)
+    0x75, 0x10, //   Report Size (16)
+    0x95, 0x06, //   Report Count (6)
+    0x81, 0x02, //   Input (Data,Var,Abs)
+    // Battery (1 byte)
+    0x05, 0x06, //   Usage Page (Generic Device Controls)
+    0x09, 0x20, //   Usage (Battery Strength)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x64, //   Logical Maximum (100)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x02, //   Input (Data,Var,Abs)
+    0xC0, // End Collection
+    // Mouse driven by the touchpad
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x02, // Usage (Mouse)
+    0xA1, 0x01, // Collection (Application)
+    0x85, 0x02, //   Report ID (2)
+    0x09, 0x01, //   Usage (Pointer)
+    0xA1, 0x00, //   Collection (Physical)
+    // Left, right and middle button (byte 1)
+    0x05, 0x09, //     Usage Page (Button)
+    0x19, 0x01, //     Usage Minimum (Button 1)
+    0x29, 0x03, //     Usage Maximum (Button 3)
+    0x15, 0x00, //     Logical Minimum (0)
+    0x25, 0x01, //     Logical Maximum (1)
+    0x75, 0x01, //     Report Size (1)
+    0x95, 0x03, //     Report Count (3)
+    0x81, 0x02, //     Input (Data,Var,Abs)
+    0x75, 0x05, //     Report Size (5)
+    0x95, 0x01, //     Report Count (1)
+    0x81, 0x03, //     Input (Const,Var,Abs)
+    // Relative movement (2 bytes)
+    0x05, 0x01, //     Usage Page (Generic Desktop)
+    0x09, 0x30, //     Usage (X)
+    0x09, 0x31, //     Usage (Y)
+    0x15, 0x81, //     Logical Minimum (-127)
+    0x25, 0x7F, //     Logical Maximum (127)
+    0x75, 0x08, //     Report Size (8)
+    0x95, 0x02, //     Report Count (2)
+    0x81, 0x06, //     Input (Data,Var,Rel)
+    0xC0, //   End Collection
+    0xC0, // End Collection
+    // Keyboard for hotkeys and typed text
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x06, // Usage (Keyboard)
+    0xA1, 0x01, // Collection (Application)
+    0x85, 0x03, //   Report ID (3)
+    // Modifiers (byte 1)
+    0x05, 0x07, //   Usage Page (Keyboard/Keypad)
+    0x19, 0xE0, //   Usage Minimum (Left Control)
+    0x29, 0xE7, //   Usage Maximum (Right GUI)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x08, //   Report Count (8)
+    0x81, 0x02, //   Input (Data,Var,Abs)
+    // Reserved (byte 2)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x03, //   Input (Const,Var,Abs)
+    // Pressed keys (6 bytes)
+    0x19, 0x00, //   Usage Minimum (0)
+    0x29, 0xFF, //   Usage Maximum (255)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x06, //   Report Count (6)
+    0x81, 0x00, //   Input (Data,Array,Abs)
     0xC0, // End Collection
 ];
 
@@ -100,17 +172,72 @@ const fn bluetooth_uuid_from_u16(uuid16: u16) -> Uuid {
     Uuid::from_u128(((uuid16 as u128) << 96) | BASE)
 }
 
+// Input report characteristic for one report id of the report map. The Report Reference
+// descriptor tells the host which id the notifications carry, so they go out without it.
+fn input_report(report_id: u8, report_rx: broadcast::Receiver<Vec<u8>>) -> Characteristic {
+    Characteristic {
+        uuid: HID_REPORT_UUID,
+        notify: Some(CharacteristicNotify {
+            notify: true,
+            indicate: false,
+            method: CharacteristicNotifyMethod::Fun(Box::new(move |mut stream| {
+                let mut report_rx = report_rx.resubscribe();
+                Box::pin(async move {
+                    while let Ok(report) = report_rx.recv().await {
+                        if let Err(e) = stream.notify(report).await {
+                            eprintln!("Failed to send notification: {}", e);
+                            break;
+                        }
+                    }
+                })
+            })),
+            _non_exhaustive: (),
+        }),
+        descriptors: vec![
+            Descriptor {
+                uuid: CCCD_UUID,
+                read: Some(DescriptorRead {
+                    read: true,
+                    ..Default::default()
+                }),
+                write: Some(DescriptorWrite {
+                    write: true,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            Descriptor {
+                uuid: REPORT_REFERENCE_UUID,
+                read: Some(DescriptorRead {
+                    read: true,
+                    // Report type 0x01: input.
+                    fun: Box::new(move |_| Box::pin(async move { Ok(vec![report_id, 0x01]) })),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    }
+}
+
 pub struct DualSenseController {
     state: Arc<Mutex<ControllerState>>,
     report_tx: Arc<Mutex<broadcast::Sender<Vec<u8>>>>,
+    mouse_tx: Arc<Mutex<broadcast::Sender<Vec<u8>>>>,
+    keyboard_tx: Arc<Mutex<broadcast::Sender<Vec<u8>>>>,
 }
 
 impl DualSenseController {
     pub fn new() -> Self {
         let (report_tx, _) = broadcast::channel(32);
+        let (mouse_tx, _) = broadcast::channel(32);
+        let (keyboard_tx, _) = broadcast::channel(32);
         Self {
             state: Arc::new(Mutex::new(ControllerState::default())),
             report_tx: Arc::new(Mutex::new(report_tx)),
+            mouse_tx: Arc::new(Mutex::new(mouse_tx)),
+            keyboard_tx: Arc::new(Mutex::new(keyboard_tx)),
         }
     }
 
@@ -129,12 +256,29 @@ impl DualSenseController {
 
     pub async fn run_report_loop(&self) {
         let report_tx = self.report_tx.lock().unwrap().clone();
+        let mouse_tx = self.mouse_tx.lock().unwrap().clone();
+        let keyboard_tx = self.keyboard_tx.lock().unwrap().clone();
+        let mut turbo = TurboClock::default();
+        let mut mouse_buttons = MouseButtons::empty();
+        let mut keyboard = Keyboard::default();
         loop {
             {
-                let state = self.state.lock().unwrap();
-                let _ = report_tx.send(state.to_bytes().to_vec());
+                let mut state = self.state.lock().unwrap();
+                let _ = report_tx.send(turbo.report(&state).to_vec());
+                // The mouse report only goes out when there is something to tell the host.
+                if state.mouse.dx != 0
+                    || state.mouse.dy != 0
+                    || state.mouse.buttons != mouse_buttons
+                {
+                    mouse_buttons = state.mouse.buttons;
+                    let _ = mouse_tx.send(state.take_mouse_report().to_vec());
+                }
+                if state.keyboard != keyboard {
+                    keyboard = state.keyboard.clone();
+                    let _ = keyboard_tx.send(state.keyboard_report().to_vec());
+                }
             }
-            sleep(Duration::from_millis(16)).await
+            sleep(REPORT_INTERVAL).await
         }
     }
 
@@ -196,40 +340,23 @@ impl DualSenseController {
             ..Default::default()
         });
 
-        // Input Report Characteristic (Notify)
+        // Gamepad Input Report Characteristic (Notify)
         let report_rx = self.report_tx.lock().unwrap().subscribe();
-        service.characteristics.push(Characteristic {
-            uuid: REPORT_CHARACTERISTIC_UUID,
-            notify: Some(CharacteristicNotify {
-                notify: true,
-                indicate: false,
-                method: CharacteristicNotifyMethod::Fun(Box::new(move |mut stream| {
-                    let mut report_rx = report_rx.resubscribe();
-                    Box::pin(async move {
-                        while let Ok(report) = report_rx.recv().await {
-                            if let Err(e) = stream.notify(report).await {
-                                eprintln!("Failed to send notification: {}", e);
-                                break;
-                            }
-                        }
-                    })
-                })),
-                _non_exhaustive: (),
-            }),
-            descriptors: vec![Descriptor {
-                uuid: CCCD_UUID,
-                read: Some(DescriptorRead {
-                    read: true,
-                    ..Default::default()
-                }),
-                write: Some(DescriptorWrite {
-                    write: true,
-                    ..Default::default()
-                }),
-                ..Default::default()
-            }],
-            ..Default::default()
-        });
+        service
+            .characteristics
+            .push(input_report(GAMEPAD_REPORT_ID, report_rx));
+
+        // Mouse Input Report Characteristic (Notify)
+        let mouse_rx = self.mouse_tx.lock().unwrap().subscribe();
+        service
+            .characteristics
+            .push(input_report(MOUSE_REPORT_ID, mouse_rx));
+
+        // Keyboard Input Report Characteristic (Notify)
+        let keyboard_rx = self.keyboard_tx.lock().unwrap().subscribe();
+        service
+            .characteristics
+            .push(input_report(KEYBOARD_REPORT_ID, keyboard_rx));
 
         // Create GATT Application
         let app = Application {
@@ -0,0 +1,1014 @@
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+use std::{
+    error, fmt, fs, io,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+#[cfg(target_os = "linux")]
+use futures_util::StreamExt;
+#[cfg(target_os = "linux")]
+use inotify::{Inotify, WatchMask};
+use serde::Deserialize;
+
+use crate::{
+    interfaces::internal::{
+        Axis2D, Buttons, CurveError, Interpolation, KeyModifiers, KeyPress, Layer, Macro,
+        MacroAction, MacroMode, Point, Profile, SensitivityProfile,
+    },
+    profile::{
+        dpad::DpadSettings,
+        filter::{FilterSettings, JitterFilter},
+        gyro::{ClutchMode, GyroOutput, GyroSettings},
+        mouse::MouseSettings,
+        stick::StickSettings,
+        tilt::TiltMapping,
+        touch::{Rect, TouchActivation, TouchMapping, TouchRegion},
+        trigger::TriggerSettings,
+        turbo::{Turbo, TurboMode},
+    },
+};
+
+/// Profiles and sensitivity curves loaded from a TOML or JSON file.
+///
+/// ```toml
+/// [[sensitivity]]
+/// name = "precise"
+/// curve = [[0, 0], [128, 100], [255, 255]]
+/// interpolation = "monotone_cubic"
+///
+/// [[profile]]
+/// name = "default"
+/// sensitivity = "precise"
+///
+/// [profile.left_stick]
+/// deadzone = 0.08
+/// anti_deadzone = 0.2
+///
+/// # Hide the overshoot when a stick is let go, smooth out noise while it rests.
+/// [profile.filter]
+/// snapback = { speed = 40, frames = 4 }
+/// jitter = { kind = "one_euro", min_cutoff = 1.0, beta = 0.5 }
+///
+/// [profile.r2]
+/// hair_trigger = 40
+///
+/// # Aim with the gyro while L2 is held.
+/// [profile.gyro]
+/// sensitivity = 0.02
+/// smoothing_ms = 20
+/// deadband = 0.5
+/// clutch = ["L2"]
+/// clutch_mode = "enable"
+///
+/// # Steer by rolling the controller, accelerate by tipping it forward.
+/// [[profile.tilt]]
+/// angle = "roll"
+/// target = "left_x"
+/// range = 60
+///
+/// [[profile.tilt]]
+/// angle = "pitch"
+/// target = "r2"
+/// neutral = 20
+/// invert = true
+///
+/// # Back paddles on the lower corners of the touchpad.
+/// [[profile.touch]]
+/// region = "bottom_left"
+/// buttons = ["L3"]
+///
+/// [[profile.touch]]
+/// rect = [0.5, 0.5, 1.0, 1.0]
+/// buttons = ["R3"]
+/// activation = "click"
+///
+/// # Move the host's mouse pointer with the touchpad, clicking it is a left click.
+/// [profile.mouse]
+/// sensitivity = 0.4
+/// acceleration = 1.5
+/// left = ["TOUCHPAD"]
+///
+/// # The left stick doubles as the D-pad, opposite directions from a fightstick cancel out.
+/// [profile.dpad]
+/// stick_to_dpad = { stick = "left", threshold = 0.6, diagonal = 30 }
+/// socd = "neutral"
+///
+/// [[profile.turbo]]
+/// buttons = ["CROSS"]
+/// press_ms = 32
+/// release_ms = 32
+/// mode = "toggle"
+///
+/// # Create takes a screenshot, holding the touchpad click is push-to-talk on V.
+/// [[profile.macro]]
+/// include = ["CREATE"]
+/// filter = ["CREATE"]
+/// actions = [{ key = { usage = 0x46 } }]
+///
+/// [[profile.macro]]
+/// include = ["TOUCHPAD"]
+/// filter = ["TOUCHPAD"]
+/// key = { usage = 0x19, modifiers = ["LEFT_CTRL"] }
+///
+/// [[profile.macro]]
+/// include = ["L1", "R1"]
+/// filter = ["L1", "R1"]
+/// switch_profile = "racing"
+/// actions = [{ press = ["CROSS"] }, { sleep = 50 }, { release = ["CROSS"] }]
+///
+/// # Holding OPTIONS for a second opens the racing profile, a short press sends nothing.
+/// [[profile.macro]]
+/// include = ["OPTIONS"]
+/// filter = ["OPTIONS"]
+/// mode = "long_press"
+/// long_press_ms = 1000
+/// switch_profile = "racing"
+///
+/// # Press R2 once to keep it held, press again to let go.
+/// [[profile.macro]]
+/// include = ["R2"]
+/// add = ["R2"]
+/// mode = "toggle"
+///
+/// # While L1 is held, CROSS sends CIRCLE and L1 itself is not sent.
+/// [[profile.layer]]
+/// name = "alt"
+/// shift = ["L1"]
+///
+/// [[profile.layer.macro]]
+/// include = ["CROSS"]
+/// filter = ["CROSS"]
+/// add = ["CIRCLE"]
+///
+/// # Steer with the D-pad, sent as full deflection of the left stick.
+/// [[profile]]
+/// name = "racing"
+///
+/// [profile.dpad]
+/// dpad_to_stick = "left"
+/// ```
+#[derive(Default)]
+pub struct Config {
+    pub profiles: Vec<Profile>,
+    pub sensitivities: Vec<SensitivityProfile>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(String),
+    NoProfiles,
+    DuplicateProfile(String),
+    DuplicateSensitivity(String),
+    UnknownButton { entry: String, name: String },
+    UnknownModifier { entry: String, name: String },
+    Untypeable { entry: String, c: char },
+    UnknownProfile { entry: String, name: String },
+    UnknownSensitivity { entry: String, name: String },
+    EmptyTrigger { entry: String },
+    EmptyShift { entry: String },
+    TouchArea { entry: String },
+    Curve { entry: String, error: CurveError },
+    OutOfRange { entry: String, field: &'static str },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "failed to read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "failed to parse config: {}", e),
+            ConfigError::NoProfiles => write!(f, "config does not define any profile"),
+            ConfigError::DuplicateProfile(name) => {
+                write!(f, "profile '{}' is defined more than once", name)
+            }
+            ConfigError::DuplicateSensitivity(name) => {
+                write!(f, "sensitivity '{}' is defined more than once", name)
+            }
+            ConfigError::UnknownButton { entry, name } => {
+                write!(f, "{}: unknown button '{}'", entry, name)
+            }
+            ConfigError::UnknownModifier { entry, name } => {
+                write!(f, "{}: unknown key modifier '{}'", entry, name)
+            }
+            ConfigError::Untypeable { entry, c } => {
+                write!(f, "{}: no key types {:?}", entry, c)
+            }
+            ConfigError::UnknownProfile { entry, name } => {
+                write!(f, "{}: unknown profile '{}'", entry, name)
+            }
+            ConfigError::UnknownSensitivity { entry, name } => {
+                write!(f, "{}: unknown sensitivity '{}'", entry, name)
+            }
+            ConfigError::EmptyTrigger { entry } => {
+                write!(f, "{}: macro needs at least one button in 'include'", entry)
+            }
+            ConfigError::EmptyShift { entry } => {
+                write!(f, "{}: layer needs at least one button in 'shift'", entry)
+            }
+            ConfigError::TouchArea { entry } => {
+                write!(f, "{}: needs exactly one of 'region' or 'rect'", entry)
+            }
+            ConfigError::Curve { entry, error } => write!(f, "{}: {}", entry, error),
+            ConfigError::OutOfRange { entry, field } => {
+                write!(f, "{}: '{}' is out of range", entry, field)
+            }
+        }
+    }
+}
+
+impl error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(value: io::Error) -> Self {
+        ConfigError::Io(value)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default, rename = "profile")]
+    profiles: Vec<ProfileEntry>,
+    #[serde(default, rename = "sensitivity")]
+    sensitivities: Vec<SensitivityEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileEntry {
+    name: String,
+    #[serde(default)]
+    sensitivity: Option<String>,
+    #[serde(default)]
+    left_stick: StickSettings,
+    #[serde(default)]
+    right_stick: StickSettings,
+    #[serde(default)]
+    l2: TriggerSettings,
+    #[serde(default)]
+    r2: TriggerSettings,
+    #[serde(default)]
+    turbo: Vec<TurboEntry>,
+    #[serde(default, rename = "macro")]
+    macros: Vec<MacroEntry>,
+    #[serde(default, rename = "layer")]
+    layers: Vec<LayerEntry>,
+    #[serde(default)]
+    gyro: Option<GyroEntry>,
+    #[serde(default)]
+    tilt: Vec<TiltMapping>,
+    #[serde(default)]
+    touch: Vec<TouchEntry>,
+    #[serde(default)]
+    mouse: Option<MouseEntry>,
+    #[serde(default)]
+    dpad: DpadSettings,
+    #[serde(default)]
+    filter: FilterSettings,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MouseEntry {
+    #[serde(default = "default_mouse_sensitivity")]
+    sensitivity: f32,
+    #[serde(default)]
+    acceleration: f32,
+    #[serde(default)]
+    left: Vec<String>,
+    #[serde(default)]
+    right: Vec<String>,
+    #[serde(default)]
+    middle: Vec<String>,
+}
+
+fn default_mouse_sensitivity() -> f32 {
+    0.5
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TouchEntry {
+    #[serde(default)]
+    region: Option<TouchRegion>,
+    /// Left, top, right, bottom as fractions of the touchpad.
+    #[serde(default)]
+    rect: Option<[f32; 4]>,
+    buttons: Vec<String>,
+    #[serde(default)]
+    activation: TouchActivation,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GyroEntry {
+    #[serde(default = "default_gyro_sensitivity")]
+    sensitivity: f32,
+    #[serde(default)]
+    smoothing_ms: u64,
+    #[serde(default)]
+    deadband: f32,
+    #[serde(default)]
+    clutch: Vec<String>,
+    #[serde(default)]
+    clutch_mode: ClutchMode,
+    #[serde(default)]
+    output: GyroOutput,
+    #[serde(default)]
+    invert_x: bool,
+    #[serde(default)]
+    invert_y: bool,
+}
+
+// Full deflection at 100 deg/s.
+fn default_gyro_sensitivity() -> f32 {
+    0.01
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayerEntry {
+    name: String,
+    shift: Vec<String>,
+    #[serde(default)]
+    sensitivity: Option<String>,
+    #[serde(default, rename = "macro")]
+    macros: Vec<MacroEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TurboEntry {
+    buttons: Vec<String>,
+    #[serde(default = "default_turbo_ms")]
+    press_ms: u64,
+    #[serde(default = "default_turbo_ms")]
+    release_ms: u64,
+    #[serde(default)]
+    mode: TurboMode,
+}
+
+fn default_turbo_ms() -> u64 {
+    50
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MacroEntry {
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    mode: MacroModeEntry,
+    #[serde(default = "default_long_press_ms")]
+    long_press_ms: u64,
+    #[serde(default)]
+    filter: Vec<String>,
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    switch_profile: Option<String>,
+    #[serde(default)]
+    hold_sensitivity: Option<String>,
+    #[serde(default)]
+    key: Option<KeyEntry>,
+    #[serde(default)]
+    actions: Option<Vec<ActionEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyEntry {
+    usage: u8,
+    #[serde(default)]
+    modifiers: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MacroModeEntry {
+    #[default]
+    Momentary,
+    Toggle,
+    LongPress,
+}
+
+fn default_long_press_ms() -> u64 {
+    500
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ActionEntry {
+    PressJoystick { left: [u8; 2], right: [u8; 2] },
+    Press(Vec<String>),
+    ReleaseJoystick,
+    Release(Vec<String>),
+    Sleep(u64),
+    Key(KeyEntry),
+    TypeText(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SensitivityEntry {
+    name: String,
+    curve: Vec<[u8; 2]>,
+    #[serde(default)]
+    interpolation: Interpolation,
+}
+
+impl Config {
+    /// Reads `path` as JSON if it has a `.json` extension, as TOML otherwise.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path)?;
+        if path.extension().is_some_and(|e| e == "json") {
+            Self::from_json(&text)
+        } else {
+            Self::from_toml(&text)
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        Self::from_file(toml::from_str(text).map_err(|e| ConfigError::Parse(e.to_string()))?)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, ConfigError> {
+        Self::from_file(serde_json::from_str(text).map_err(|e| ConfigError::Parse(e.to_string()))?)
+    }
+
+    fn from_file(file: ConfigFile) -> Result<Self, ConfigError> {
+        if file.profiles.is_empty() {
+            return Err(ConfigError::NoProfiles);
+        }
+
+        let mut sensitivities = Vec::with_capacity(file.sensitivities.len());
+        for entry in file.sensitivities {
+            if sensitivities
+                .iter()
+                .any(|s: &SensitivityProfile| s.name == entry.name)
+            {
+                return Err(ConfigError::DuplicateSensitivity(entry.name));
+            }
+            sensitivities.push(parse_sensitivity(entry)?);
+        }
+
+        let profile_names = file
+            .profiles
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect::<Vec<_>>();
+        for (i, name) in profile_names.iter().enumerate() {
+            if profile_names[..i].contains(name) {
+                return Err(ConfigError::DuplicateProfile(name.to_string()));
+            }
+        }
+
+        let check_sensitivity = |entry: &str, name: &str| {
+            if sensitivities.iter().any(|s| s.name == name) {
+                Ok(())
+            } else {
+                Err(ConfigError::UnknownSensitivity {
+                    entry: entry.to_string(),
+                    name: name.to_string(),
+                })
+            }
+        };
+
+        let mut profiles = Vec::with_capacity(file.profiles.len());
+        for profile in &file.profiles {
+            let entry = format!("profile '{}'", profile.name);
+            if let Some(name) = &profile.sensitivity {
+                check_sensitivity(&entry, name)?;
+            }
+            check_stick(&format!("{} left_stick", entry), &profile.left_stick)?;
+            check_stick(&format!("{} right_stick", entry), &profile.right_stick)?;
+            for (field, trigger) in [("l2", &profile.l2), ("r2", &profile.r2)] {
+                let entry = format!("{} {}", entry, field);
+                if trigger.start >= trigger.end {
+                    return Err(ConfigError::OutOfRange {
+                        entry,
+                        field: "start",
+                    });
+                }
+                // A threshold of 0 would report the trigger pressed while it rests.
+                if trigger.hair_trigger == Some(0) {
+                    return Err(ConfigError::OutOfRange {
+                        entry,
+                        field: "hair_trigger",
+                    });
+                }
+                if let Some(name) = &trigger.curve {
+                    check_sensitivity(&entry, name)?;
+                }
+            }
+
+            let mut turbo = Vec::with_capacity(profile.turbo.len());
+            for (i, t) in profile.turbo.iter().enumerate() {
+                let entry = format!("profile '{}' turbo #{}", profile.name, i + 1);
+                let buttons = parse_buttons(&entry, &t.buttons)?;
+                turbo.push(Turbo::from_millis(
+                    buttons,
+                    t.press_ms,
+                    t.release_ms,
+                    t.mode,
+                ));
+            }
+
+            let parse_macros = |entry: &str, entries: &[MacroEntry]| {
+                let mut macros = Vec::with_capacity(entries.len());
+                for (i, m) in entries.iter().enumerate() {
+                    let entry = format!("{} macro #{}", entry, i + 1);
+                    if let Some(name) = &m.switch_profile
+                        && !profile_names.contains(&name.as_str())
+                    {
+                        return Err(ConfigError::UnknownProfile {
+                            entry,
+                            name: name.clone(),
+                        });
+                    }
+                    if let Some(name) = &m.hold_sensitivity {
+                        check_sensitivity(&entry, name)?;
+                    }
+                    macros.push(parse_macro(&entry, m)?);
+                }
+                Ok(macros)
+            };
+            let macros = parse_macros(&entry, &profile.macros)?;
+
+            let mut layers = Vec::with_capacity(profile.layers.len());
+            for layer in &profile.layers {
+                let entry = format!("{} layer '{}'", entry, layer.name);
+                let shift = parse_buttons(&entry, &layer.shift)?;
+                if shift.is_empty() {
+                    return Err(ConfigError::EmptyShift { entry });
+                }
+                if let Some(name) = &layer.sensitivity {
+                    check_sensitivity(&entry, name)?;
+                }
+                layers.push(Layer {
+                    name: layer.name.clone(),
+                    shift,
+                    sensitivity: layer.sensitivity.clone(),
+                    macros: parse_macros(&entry, &layer.macros)?,
+                });
+            }
+
+            let gyro = match &profile.gyro {
+                Some(g) => {
+                    let entry = format!("{} gyro", entry);
+                    for (field, value) in [("sensitivity", g.sensitivity), ("deadband", g.deadband)]
+                    {
+                        if !(value >= 0.0 && value.is_finite()) {
+                            return Err(ConfigError::OutOfRange { entry, field });
+                        }
+                    }
+                    Some(GyroSettings {
+                        sensitivity: g.sensitivity,
+                        smoothing: Duration::from_millis(g.smoothing_ms),
+                        deadband: g.deadband,
+                        clutch: parse_buttons(&entry, &g.clutch)?,
+                        clutch_mode: g.clutch_mode,
+                        output: g.output,
+                        invert_x: g.invert_x,
+                        invert_y: g.invert_y,
+                    })
+                }
+                None => None,
+            };
+
+            for (i, t) in profile.tilt.iter().enumerate() {
+                let entry = format!("{} tilt #{}", entry, i + 1);
+                let checks = [
+                    ("neutral", (-180.0..=180.0).contains(&t.neutral)),
+                    ("range", t.range > 0.0 && t.range <= 180.0),
+                    ("deadzone", t.deadzone >= 0.0 && t.deadzone < t.range),
+                ];
+                if let Some((field, _)) = checks.into_iter().find(|(_, ok)| !ok) {
+                    return Err(ConfigError::OutOfRange { entry, field });
+                }
+            }
+
+            let mut touch = Vec::with_capacity(profile.touch.len());
+            for (i, t) in profile.touch.iter().enumerate() {
+                let entry = format!("{} touch #{}", entry, i + 1);
+                let rect = match (t.region, t.rect) {
+                    (Some(region), None) => region.rect(),
+                    (None, Some([left, top, right, bottom])) => {
+                        let valid = (0.0 <= left && left < right && right <= 1.0)
+                            && (0.0 <= top && top < bottom && bottom <= 1.0);
+                        if !valid {
+                            return Err(ConfigError::OutOfRange {
+                                entry,
+                                field: "rect",
+                            });
+                        }
+                        Rect {
+                            left,
+                            top,
+                            right,
+                            bottom,
+                        }
+                    }
+                    _ => return Err(ConfigError::TouchArea { entry }),
+                };
+                touch.push(TouchMapping {
+                    rect,
+                    buttons: parse_buttons(&entry, &t.buttons)?,
+                    activation: t.activation,
+                });
+            }
+
+            let mouse = match &profile.mouse {
+                Some(m) => {
+                    let entry = format!("{} mouse", entry);
+                    for (field, value) in [
+                        ("sensitivity", m.sensitivity),
+                        ("acceleration", m.acceleration),
+                    ] {
+                        if !(value >= 0.0 && value.is_finite()) {
+                            return Err(ConfigError::OutOfRange { entry, field });
+                        }
+                    }
+                    Some(MouseSettings {
+                        sensitivity: m.sensitivity,
+                        acceleration: m.acceleration,
+                        left: parse_buttons(&entry, &m.left)?,
+                        right: parse_buttons(&entry, &m.right)?,
+                        middle: parse_buttons(&entry, &m.middle)?,
+                    })
+                }
+                None => None,
+            };
+
+            if let Some(s) = &profile.dpad.stick_to_dpad {
+                let entry = format!("{} dpad", entry);
+                check_fraction(&entry, "threshold", s.threshold)?;
+                if !(0.0..=90.0).contains(&s.diagonal) {
+                    return Err(ConfigError::OutOfRange {
+                        entry,
+                        field: "diagonal",
+                    });
+                }
+            }
+
+            let filter = &profile.filter;
+            let checks = [
+                (
+                    "snapback",
+                    filter
+                        .snapback
+                        .is_none_or(|s| s.speed > 0.0 && s.speed.is_finite()),
+                ),
+                (
+                    "jitter",
+                    match filter.jitter {
+                        Some(JitterFilter::Ema { alpha }) => alpha > 0.0 && alpha <= 1.0,
+                        Some(JitterFilter::OneEuro { min_cutoff, beta }) => {
+                            (min_cutoff > 0.0 && min_cutoff.is_finite())
+                                && (beta >= 0.0 && beta.is_finite())
+                        }
+                        None => true,
+                    },
+                ),
+            ];
+            if let Some((field, _)) = checks.into_iter().find(|(_, ok)| !ok) {
+                return Err(ConfigError::OutOfRange {
+                    entry: format!("{} filter", entry),
+                    field,
+                });
+            }
+
+            profiles.push(Profile {
+                name: profile.name.clone(),
+                sensitivity: profile.sensitivity.clone().unwrap_or_default(),
+                left_stick: profile.left_stick,
+                right_stick: profile.right_stick,
+                l2: profile.l2.clone(),
+                r2: profile.r2.clone(),
+                turbo,
+                macros,
+                layers,
+                gyro,
+                tilt: profile.tilt.clone(),
+                touch,
+                mouse,
+                dpad: profile.dpad,
+                filter: profile.filter,
+            });
+        }
+
+        Ok(Config {
+            profiles,
+            sensitivities,
+        })
+    }
+}
+
+/// A freshly loaded config waiting for the `ProfileEngine` to pick it up between two frames.
+pub type PendingConfig = Arc<Mutex<Option<Config>>>;
+
+/// Reloads the config into `pending` whenever the file at `path` is written or replaced.
+///
+/// The parent directory is watched rather than the file itself, so editors that save by
+/// renaming a temporary file over the original are picked up as well. A config that fails to
+/// load is reported and ignored, leaving the last good one active.
+#[cfg(target_os = "linux")]
+pub async fn watch(path: PathBuf, pending: PendingConfig) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let Some(file_name) = path.file_name().map(|n| n.to_os_string()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "config path has no file name",
+        ));
+    };
+
+    let inotify = Inotify::init()?;
+    inotify
+        .watches()
+        .add(&dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+    let mut events = inotify.into_event_stream([0u8; 1024])?;
+
+    while let Some(event) = events.next().await {
+        if event?.name.as_ref() != Some(&file_name) {
+            continue;
+        }
+        match Config::load(&path) {
+            Ok(config) => {
+                println!(
+                    "Reloaded {} profile(s) from {}",
+                    config.profiles.len(),
+                    path.display()
+                );
+                *pending.lock().unwrap() = Some(config);
+            }
+            Err(e) => eprintln!("Keeping previous config: {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn check_fraction(entry: &str, field: &'static str, value: f32) -> Result<(), ConfigError> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(())
+    } else {
+        Err(ConfigError::OutOfRange {
+            entry: entry.to_string(),
+            field,
+        })
+    }
+}
+
+fn check_stick(entry: &str, stick: &StickSettings) -> Result<(), ConfigError> {
+    check_fraction(entry, "deadzone", stick.deadzone)?;
+    check_fraction(entry, "outer_deadzone", stick.outer_deadzone)?;
+    check_fraction(entry, "anti_deadzone", stick.anti_deadzone)?;
+    if stick.deadzone + stick.outer_deadzone >= 1.0 {
+        return Err(ConfigError::OutOfRange {
+            entry: entry.to_string(),
+            field: "outer_deadzone",
+        });
+    }
+    Ok(())
+}
+
+fn parse_buttons(entry: &str, names: &[String]) -> Result<Buttons, ConfigError> {
+    names.iter().try_fold(Buttons::empty(), |acc, name| {
+        Buttons::from_name(name)
+            .map(|b| acc | b)
+            .ok_or_else(|| ConfigError::UnknownButton {
+                entry: entry.to_string(),
+                name: name.clone(),
+            })
+    })
+}
+
+fn parse_macro(entry: &str, m: &MacroEntry) -> Result<Macro, ConfigError> {
+    let include = parse_buttons(entry, &m.include)?;
+    if include.is_empty() {
+        return Err(ConfigError::EmptyTrigger {
+            entry: entry.to_string(),
+        });
+    }
+
+    let macro_list = match &m.actions {
+        Some(actions) => Some(
+            actions
+                .iter()
+                .map(|a| parse_action(entry, a))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        None => None,
+    };
+
+    let mode = match m.mode {
+        MacroModeEntry::Momentary => MacroMode::Momentary,
+        MacroModeEntry::Toggle => MacroMode::Toggle,
+        MacroModeEntry::LongPress => MacroMode::LongPress(Duration::from_millis(m.long_press_ms)),
+    };
+
+    Ok(Macro {
+        include,
+        exclude: parse_buttons(entry, &m.exclude)?,
+        mode,
+        filter: parse_buttons(entry, &m.filter)?,
+        add: parse_buttons(entry, &m.add)?,
+        switch_profile: m.switch_profile.clone(),
+        hold_sensitivity: m.hold_sensitivity.clone(),
+        key: m.key.as_ref().map(|k| parse_key(entry, k)).transpose()?,
+        macro_list,
+    })
+}
+
+fn parse_action(entry: &str, action: &ActionEntry) -> Result<MacroAction, ConfigError> {
+    Ok(match action {
+        ActionEntry::PressJoystick { left, right } => MacroAction::PressJoystick {
+            left: Axis2D {
+                x: left[0],
+                y: left[1],
+            },
+            right: Axis2D {
+                x: right[0],
+                y: right[1],
+            },
+        },
+        ActionEntry::Press(names) => MacroAction::Press(parse_buttons(entry, names)?),
+        ActionEntry::ReleaseJoystick => MacroAction::ReleaseJoystick,
+        ActionEntry::Release(names) => MacroAction::Release(parse_buttons(entry, names)?),
+        ActionEntry::Sleep(ms) => MacroAction::Sleep(*ms),
+        ActionEntry::Key(key) => {
+            let KeyPress { usage, modifiers } = parse_key(entry, key)?;
+            MacroAction::Key { usage, modifiers }
+        }
+        ActionEntry::TypeText(text) => {
+            if let Some(c) = text.chars().find(|c| KeyPress::from_char(*c).is_none()) {
+                return Err(ConfigError::Untypeable {
+                    entry: entry.to_string(),
+                    c,
+                });
+            }
+            MacroAction::TypeText(text.clone())
+        }
+    })
+}
+
+fn parse_key(entry: &str, key: &KeyEntry) -> Result<KeyPress, ConfigError> {
+    let modifiers = key
+        .modifiers
+        .iter()
+        .try_fold(KeyModifiers::empty(), |acc, name| {
+            KeyModifiers::from_name(name)
+                .map(|m| acc | m)
+                .ok_or_else(|| ConfigError::UnknownModifier {
+                    entry: entry.to_string(),
+                    name: name.clone(),
+                })
+        })?;
+    Ok(KeyPress {
+        usage: key.usage,
+        modifiers,
+    })
+}
+
+fn parse_sensitivity(entry: SensitivityEntry) -> Result<SensitivityProfile, ConfigError> {
+    let name = format!("sensitivity '{}'", entry.name);
+    let curve = entry.curve.iter().map(|&[x, y]| Point { x, y }).collect();
+    SensitivityProfile::new(entry.name, curve, entry.interpolation)
+        .map_err(|error| ConfigError::Curve { entry: name, error })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The TOML block of the `Config` doc comment.
+    fn doc_example() -> String {
+        include_str!("config.rs")
+            .lines()
+            .skip_while(|l| *l != "/// ```toml")
+            .skip(1)
+            .take_while(|l| *l != "/// ```")
+            .map(|l| format!("{}\n", l.strip_prefix("/// ").unwrap_or("")))
+            .collect()
+    }
+
+    fn error(text: &str) -> String {
+        match Config::from_toml(text) {
+            Ok(_) => panic!("config was accepted:\n{}", text),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn doc_example_parses() {
+        let config = Config::from_toml(&doc_example()).unwrap_or_else(|e| panic!("{}", e));
+        let names: Vec<_> = config.profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["default", "racing"]);
+        assert_eq!(config.sensitivities.len(), 1);
+        let default = &config.profiles[0];
+        assert_eq!(default.layers.len(), 1);
+        assert!(default.gyro.is_some() && default.mouse.is_some());
+        assert_eq!(default.tilt.len(), 2);
+        assert_eq!(default.touch.len(), 2);
+    }
+
+    #[test]
+    fn unsorted_curve_names_the_sensitivity() {
+        let e = error(
+            r#"
+            [[sensitivity]]
+            name = "bumpy"
+            curve = [[0, 0], [200, 100], [100, 150], [255, 255]]
+
+            [[profile]]
+            name = "default"
+            "#,
+        );
+        assert!(e.starts_with("sensitivity 'bumpy': "), "{}", e);
+        assert!(e.contains("not sorted"), "{}", e);
+    }
+
+    #[test]
+    fn short_curve_names_the_sensitivity() {
+        let e = error(
+            r#"
+            [[sensitivity]]
+            name = "short"
+            curve = [[0, 0], [200, 255]]
+
+            [[profile]]
+            name = "default"
+            "#,
+        );
+        assert!(e.starts_with("sensitivity 'short': "), "{}", e);
+        assert!(e.contains("x = 255"), "{}", e);
+    }
+
+    #[test]
+    fn out_of_range_curve_point_is_rejected() {
+        let e = error(
+            r#"
+            [[sensitivity]]
+            name = "wide"
+            curve = [[0, 0], [300, 255]]
+
+            [[profile]]
+            name = "default"
+            "#,
+        );
+        assert!(e.contains("curve") && e.contains("300"), "{}", e);
+    }
+
+    #[test]
+    fn unknown_switch_profile_names_the_macro() {
+        let e = error(
+            r#"
+            [[profile]]
+            name = "default"
+
+            [[profile.macro]]
+            include = ["L1"]
+
+            [[profile.macro]]
+            include = ["R1"]
+            switch_profile = "missing"
+            "#,
+        );
+        assert_eq!(e, "profile 'default' macro #2: unknown profile 'missing'");
+    }
+
+    #[test]
+    fn zero_hair_trigger_names_the_trigger() {
+        let e = error(
+            r#"
+            [[profile]]
+            name = "default"
+
+            [profile.r2]
+            hair_trigger = 0
+            "#,
+        );
+        assert_eq!(e, "profile 'default' r2: 'hair_trigger' is out of range");
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let e = error(
+            r#"
+            [[profile]]
+            name = "default"
+
+            [profile.left_stick]
+            deadzone = 0.1
+            dead_zone = 0.2
+            "#,
+        );
+        assert!(e.contains("unknown field `dead_zone`"), "{}", e);
+    }
+}
@@ -1,6 +1,15 @@
+use std::time::Duration;
+
 use bitflags::bitflags;
 
-use super::internal::{Buttons, ControllerStateInternal};
+use super::internal::{Buttons, ControllerStateInternal, Keyboard, Mouse, PowerState, TurboSlot};
+
+pub const GAMEPAD_REPORT_ID: u8 = 0x01;
+pub const MOUSE_REPORT_ID: u8 = 0x02;
+pub const KEYBOARD_REPORT_ID: u8 = 0x03;
+
+/// How often the report loop sends the current state to the host.
+pub const REPORT_INTERVAL: Duration = Duration::from_millis(16);
 
 bitflags! {
     #[derive(Debug, Clone, Copy, Default)]
@@ -31,11 +40,12 @@ bitflags! {
     pub struct ButtonsByte7: u8 {
         const PS         = 1 << 0;
         const TOUCHPAD   = 1 << 1;
-        // Remaining 6 bits are vendor-defined and can be set as a raw value
+        const MUTE       = 1 << 2;
+        // Remaining 5 bits are vendor-defined and can be set as a raw value
     }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct ControllerState {
     pub left_stick_x: u8,
     pub left_stick_y: u8,
@@ -45,9 +55,20 @@ pub struct ControllerState {
     pub buttons_5: ButtonsByte5,
     pub buttons_6: ButtonsByte6,
     pub buttons_7: ButtonsByte7,
-    pub vendor_defined: u8, // Only lower 6 bits used
+    pub vendor_defined: u8, // Only lower 5 bits used
     pub l2_axis: u8,
     pub r2_axis: u8,
+    pub gyro_x: i16,
+    pub gyro_y: i16,
+    pub gyro_z: i16,
+    pub accel_x: i16,
+    pub accel_y: i16,
+    pub accel_z: i16,
+    pub battery: u8, // 0 - 100 %
+    pub turbo: Vec<TurboSlot>,
+    /// Mouse buttons and the movement not sent to the host yet.
+    pub mouse: Mouse,
+    pub keyboard: Keyboard,
 }
 
 fn convert_from_buttons(buttons: Buttons) -> (ButtonsByte5, ButtonsByte6, ButtonsByte7, u8) {
@@ -95,33 +116,67 @@ fn convert_from_buttons(buttons: Buttons) -> (ButtonsByte5, ButtonsByte6, Button
         byte6 |= ButtonsByte6::R3;
     }
 
-    // Byte 7 (PS & touchpad)
+    // Byte 7 (PS, touchpad & mute)
     if buttons.contains(Buttons::PS) {
         byte7 |= ButtonsByte7::PS;
     }
     if buttons.contains(Buttons::TOUCHPAD) {
         byte7 |= ButtonsByte7::TOUCHPAD;
     }
+    if buttons.contains(Buttons::MUTE) {
+        byte7 |= ButtonsByte7::MUTE;
+    }
 
     // Hat (D-pad)
+    let hat = encode_hat(buttons);
+
+    (byte5, byte6, byte7, hat)
+}
+
+// HID hat switch: 0 is north, counting clockwise in steps of 45 degrees, 8 is the null state.
+fn encode_hat(buttons: Buttons) -> u8 {
     let up = buttons.contains(Buttons::HAT_UP);
     let down = buttons.contains(Buttons::HAT_DOWN);
     let left = buttons.contains(Buttons::HAT_LEFT);
     let right = buttons.contains(Buttons::HAT_RIGHT);
 
-    let hat = match (up, down, left, right) {
-        (true, false, false, false) => 7,
-        (true, false, true, false) => 6,
-        (false, false, true, false) => 5,
-        (false, true, true, false) => 4,
-        (false, true, false, false) => 3,
-        (false, true, false, true) => 2,
-        (false, false, false, true) => 1,
-        (true, false, false, true) => 0,
+    match (up, down, left, right) {
+        (true, false, false, false) => 0,
+        (true, false, false, true) => 1,
+        (false, false, false, true) => 2,
+        (false, true, false, true) => 3,
+        (false, true, false, false) => 4,
+        (false, true, true, false) => 5,
+        (false, false, true, false) => 6,
+        (true, false, true, false) => 7,
         _ => 8, // neutral or invalid combo
-    };
+    }
+}
 
-    (byte5, byte6, byte7, hat)
+fn decode_hat(hat: u8) -> Buttons {
+    match hat {
+        0 => Buttons::HAT_UP,
+        1 => Buttons::HAT_UP | Buttons::HAT_RIGHT,
+        2 => Buttons::HAT_RIGHT,
+        3 => Buttons::HAT_DOWN | Buttons::HAT_RIGHT,
+        4 => Buttons::HAT_DOWN,
+        5 => Buttons::HAT_DOWN | Buttons::HAT_LEFT,
+        6 => Buttons::HAT_LEFT,
+        7 => Buttons::HAT_UP | Buttons::HAT_LEFT,
+        _ => Buttons::empty(),
+    }
+}
+
+// The controllers report the charge as 0 - 10, see hid-playstation. The abnormal and error
+// states carry no usable charge.
+fn battery_percent(battery: u8, power_state: &PowerState) -> u8 {
+    match power_state.bits() {
+        bits if bits == PowerState::Complete.bits() => 100,
+        bits if bits == PowerState::Discharging.bits() || bits == PowerState::Charging.bits() => {
+            (battery * 10 + 5).min(100)
+        }
+        _ => 0,
+    }
 }
 
 impl From<ControllerStateInternal> for ControllerState {
@@ -136,34 +191,329 @@ impl From<ControllerStateInternal> for ControllerState {
             buttons_5: b5,
             buttons_6: b6,
             buttons_7: b7,
-            vendor_defined: 0 & 0x3F,
+            vendor_defined: 0,
             l2_axis: value.l2_axis,
             r2_axis: value.r2_axis,
+            gyro_x: value.gyro.x,
+            gyro_y: value.gyro.y,
+            gyro_z: value.gyro.z,
+            accel_x: value.accel.x,
+            accel_y: value.accel.y,
+            accel_z: value.accel.z,
+            battery: battery_percent(value.battery, &value.power_state),
+            turbo: value.turbo,
+            mouse: value.mouse,
+            keyboard: value.keyboard,
         }
     }
 }
 
 impl ControllerState {
-    pub fn to_bytes(&self) -> [u8; 10] {
+    /// Copy of this state with `buttons` released, including their analog trigger axes.
+    fn without(&self, buttons: Buttons) -> ControllerState {
+        let (b5, b6, b7, _) = convert_from_buttons(buttons);
+        let mut state = self.clone();
+        state.buttons_5 -= b5;
+        state.buttons_6 -= b6;
+        state.buttons_7 -= b7;
+        state.hat = encode_hat(decode_hat(self.hat) - buttons);
+        if buttons.contains(Buttons::L2) {
+            state.l2_axis = 0;
+        }
+        if buttons.contains(Buttons::R2) {
+            state.r2_axis = 0;
+        }
+        state
+    }
+
+    /// Gamepad report without the report id, the gamepad has its own characteristic.
+    pub fn to_bytes(&self) -> [u8; 22] {
         let mut byte5 = self.hat & 0x0F; // lower 4 bits for HAT
         byte5 |= self.buttons_5.bits();
 
         let byte6 = self.buttons_6.bits();
 
-        let mut byte7 = self.buttons_7.bits() & 0x03; // PS and Touchpad
-        byte7 |= (self.vendor_defined & 0x3F) << 2;
+        let mut byte7 = self.buttons_7.bits() & 0x07; // PS, Touchpad and Mute
+        byte7 |= (self.vendor_defined & 0x1F) << 3;
+
+        let [gx0, gx1] = self.gyro_x.to_le_bytes();
+        let [gy0, gy1] = self.gyro_y.to_le_bytes();
+        let [gz0, gz1] = self.gyro_z.to_le_bytes();
+        let [ax0, ax1] = self.accel_x.to_le_bytes();
+        let [ay0, ay1] = self.accel_y.to_le_bytes();
+        let [az0, az1] = self.accel_z.to_le_bytes();
 
         [
-            0x01,               // Report ID
             self.left_stick_x,  // Left stick X
             self.left_stick_y,  // Left stick Y
             self.right_stick_x, // Right stick X
             self.right_stick_y, // Right stick Y
             byte5,              // Hat + face buttons
             byte6,              // Shoulder + meta buttons
-            byte7,              // PS, Touchpad, Vendor-defined
+            byte7,              // PS, Touchpad, Mute, Vendor-defined
             self.l2_axis,       // L2 analog axis
             self.r2_axis,       // R2 analog axis
+            gx0,                // Gyro X (low byte)
+            gx1,                // Gyro X (high byte)
+            gy0,                // Gyro Y (low byte)
+            gy1,                // Gyro Y (high byte)
+            gz0,                // Gyro Z (low byte)
+            gz1,                // Gyro Z (high byte)
+            ax0,                // Accel X (low byte)
+            ax1,                // Accel X (high byte)
+            ay0,                // Accel Y (low byte)
+            ay1,                // Accel Y (high byte)
+            az0,                // Accel Z (low byte)
+            az1,                // Accel Z (high byte)
+            self.battery,       // Battery strength
         ]
     }
+
+    /// Takes as much of the pending mouse movement as fits into one report, the rest is left
+    /// for the next one. The report id is left out, the mouse has its own characteristic.
+    pub fn take_mouse_report(&mut self) -> [u8; 3] {
+        let dx = self.mouse.dx.clamp(-127, 127);
+        let dy = self.mouse.dy.clamp(-127, 127);
+        self.mouse.dx -= dx;
+        self.mouse.dy -= dy;
+        [self.mouse.buttons.bits(), dx as i8 as u8, dy as i8 as u8]
+    }
+
+    /// Keyboard report without the report id, the keyboard has its own characteristic.
+    pub fn keyboard_report(&self) -> [u8; 8] {
+        let mut report = [0u8; 8];
+        report[0] = self.keyboard.modifiers.bits();
+        // Byte 1 is reserved, the pressed keys follow.
+        for (slot, usage) in report[2..].iter_mut().zip(&self.keyboard.keys) {
+            *slot = *usage;
+        }
+        report
+    }
+}
+
+/// Counts report loop ticks so turbo edges land exactly on the notifications that go out.
+#[derive(Debug, Default)]
+pub struct TurboClock {
+    tick: u64,
+    // Tick at which each turbo slot started firing, and the buttons it was firing then.
+    started: Vec<Option<(u64, Buttons)>>,
+}
+
+impl TurboClock {
+    pub fn report(&mut self, state: &ControllerState) -> [u8; 22] {
+        self.started.resize(state.turbo.len(), None);
+
+        let mut released = Buttons::empty();
+        for (slot, started) in state.turbo.iter().zip(self.started.iter_mut()) {
+            if slot.buttons.is_empty() {
+                *started = None;
+                continue;
+            }
+            // A changed set restarts the phase so a newly added button fires its press first.
+            if started.is_none_or(|(_, buttons)| buttons != slot.buttons) {
+                *started = Some((self.tick, slot.buttons));
+            }
+            let start = started.map_or(self.tick, |(tick, _)| tick);
+            let period = slot.press_ticks as u64 + slot.release_ticks as u64;
+            if (self.tick - start) % period >= slot.press_ticks as u64 {
+                released |= slot.buttons;
+            }
+        }
+        self.tick += 1;
+
+        if released.is_empty() {
+            state.to_bytes()
+        } else {
+            state.without(released).to_bytes()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::{internal::KeyPress, usb::ParsedInput};
+
+    // Builds a DualSense USB input report with the given buttons held.
+    fn ps5_report(buttons: &Buttons) -> [u8; 64] {
+        let mut buf = [0u8; 64];
+        buf[0] = 0x01;
+        let up = buttons.contains(Buttons::HAT_UP);
+        let down = buttons.contains(Buttons::HAT_DOWN);
+        let left = buttons.contains(Buttons::HAT_LEFT);
+        let right = buttons.contains(Buttons::HAT_RIGHT);
+        buf[8] = match (up, down, left, right) {
+            (true, false, false, false) => 0,
+            (true, false, false, true) => 1,
+            (false, false, false, true) => 2,
+            (false, true, false, true) => 3,
+            (false, true, false, false) => 4,
+            (false, true, true, false) => 5,
+            (false, false, true, false) => 6,
+            (true, false, true, false) => 7,
+            _ => 8,
+        };
+        let bits = [
+            (Buttons::SQUARE, 8, 4),
+            (Buttons::CROSS, 8, 5),
+            (Buttons::CIRCLE, 8, 6),
+            (Buttons::TRIANGLE, 8, 7),
+            (Buttons::L1, 9, 0),
+            (Buttons::R1, 9, 1),
+            (Buttons::L2, 9, 2),
+            (Buttons::R2, 9, 3),
+            (Buttons::CREATE, 9, 4),
+            (Buttons::OPTIONS, 9, 5),
+            (Buttons::L3, 9, 6),
+            (Buttons::R3, 9, 7),
+            (Buttons::PS, 10, 0),
+            (Buttons::TOUCHPAD, 10, 1),
+            (Buttons::MUTE, 10, 2),
+        ];
+        for (flag, byte, bit) in bits {
+            if buttons.contains(flag) {
+                buf[byte] |= 1 << bit;
+            }
+        }
+        buf
+    }
+
+    // Reads the buttons back out of an emitted report, following the layout of `to_bytes`.
+    fn report_buttons(report: &[u8]) -> Buttons {
+        // HID hat switch values, 0 = N clockwise to 7 = NW.
+        let mut buttons = match report[4] & 0x0F {
+            0 => Buttons::HAT_UP,
+            1 => Buttons::HAT_UP | Buttons::HAT_RIGHT,
+            2 => Buttons::HAT_RIGHT,
+            3 => Buttons::HAT_DOWN | Buttons::HAT_RIGHT,
+            4 => Buttons::HAT_DOWN,
+            5 => Buttons::HAT_DOWN | Buttons::HAT_LEFT,
+            6 => Buttons::HAT_LEFT,
+            7 => Buttons::HAT_UP | Buttons::HAT_LEFT,
+            _ => Buttons::empty(),
+        };
+        let bits = [
+            (Buttons::SQUARE, 4, 4),
+            (Buttons::CROSS, 4, 5),
+            (Buttons::CIRCLE, 4, 6),
+            (Buttons::TRIANGLE, 4, 7),
+            (Buttons::L1, 5, 0),
+            (Buttons::R1, 5, 1),
+            (Buttons::L2, 5, 2),
+            (Buttons::R2, 5, 3),
+            (Buttons::CREATE, 5, 4),
+            (Buttons::OPTIONS, 5, 5),
+            (Buttons::L3, 5, 6),
+            (Buttons::R3, 5, 7),
+            (Buttons::PS, 6, 0),
+            (Buttons::TOUCHPAD, 6, 1),
+            (Buttons::MUTE, 6, 2),
+        ];
+        for (flag, byte, bit) in bits {
+            if report[byte] & (1 << bit) != 0 {
+                buttons |= flag;
+            }
+        }
+        buttons
+    }
+
+    fn round_trip(buttons: &Buttons) -> Buttons {
+        let parsed = ParsedInput::from_ps5_buf(&ps5_report(buttons)).unwrap();
+        let state = ControllerState::from(ControllerStateInternal::from(parsed));
+        report_buttons(&state.to_bytes())
+    }
+
+    #[test]
+    fn every_button_survives_round_trip() {
+        for (name, flag) in Buttons::all().iter_names() {
+            assert_eq!(round_trip(&flag), flag, "{} was lost", name);
+        }
+    }
+
+    #[test]
+    fn all_buttons_survive_round_trip() {
+        let buttons = Buttons::all() - Buttons::HAT_DOWN - Buttons::HAT_LEFT;
+        assert_eq!(round_trip(&buttons), buttons);
+    }
+
+    #[test]
+    fn turbo_restarts_when_its_buttons_change() {
+        let state = |buttons| ControllerState {
+            turbo: vec![TurboSlot {
+                buttons,
+                press_ticks: 2,
+                release_ticks: 2,
+            }],
+            ..ControllerState::from({
+                let parsed = ParsedInput::from_ps5_buf(&ps5_report(&buttons)).unwrap();
+                ControllerStateInternal::from(parsed)
+            })
+        };
+        let mut clock = TurboClock::default();
+        let cross = state(Buttons::CROSS);
+        let pressed: Vec<_> = (0..3)
+            .map(|_| report_buttons(&clock.report(&cross)))
+            .collect();
+        assert_eq!(pressed, [Buttons::CROSS, Buttons::CROSS, Buttons::empty()]);
+
+        // Mid release phase of CROSS: adding CIRCLE presses both right away.
+        let both = state(Buttons::CROSS | Buttons::CIRCLE);
+        assert_eq!(
+            report_buttons(&clock.report(&both)),
+            Buttons::CROSS | Buttons::CIRCLE
+        );
+    }
+
+    #[test]
+    fn hat_uses_hid_hat_switch_values() {
+        let hat = |buttons| {
+            let parsed = ParsedInput::from_ps5_buf(&ps5_report(&Buttons::empty())).unwrap();
+            let mut state = ControllerStateInternal::from(parsed);
+            state.button = buttons;
+            ControllerState::from(state).to_bytes()[4] & 0x0F
+        };
+        assert_eq!(hat(Buttons::empty()), 8);
+        assert_eq!(hat(Buttons::HAT_UP), 0);
+        assert_eq!(hat(Buttons::HAT_UP | Buttons::HAT_RIGHT), 1);
+        assert_eq!(hat(Buttons::HAT_RIGHT), 2);
+        assert_eq!(hat(Buttons::HAT_DOWN | Buttons::HAT_RIGHT), 3);
+        assert_eq!(hat(Buttons::HAT_DOWN), 4);
+        assert_eq!(hat(Buttons::HAT_DOWN | Buttons::HAT_LEFT), 5);
+        assert_eq!(hat(Buttons::HAT_LEFT), 6);
+        assert_eq!(hat(Buttons::HAT_UP | Buttons::HAT_LEFT), 7);
+        assert_eq!(hat(Buttons::HAT_UP | Buttons::HAT_DOWN), 8);
+    }
+
+    #[test]
+    fn error_power_states_report_no_charge() {
+        assert_eq!(battery_percent(4, &PowerState::Discharging), 45);
+        assert_eq!(battery_percent(10, &PowerState::Charging), 100);
+        assert_eq!(battery_percent(3, &PowerState::Complete), 100);
+        assert_eq!(battery_percent(8, &PowerState::AbnormalVoltage), 0);
+        assert_eq!(battery_percent(8, &PowerState::AbnormalTemperature), 0);
+        assert_eq!(battery_percent(8, &PowerState::ChargingError), 0);
+    }
+
+    #[test]
+    fn mute_does_not_leak_into_vendor_bits() {
+        let state = ControllerState {
+            buttons_7: ButtonsByte7::MUTE,
+            vendor_defined: 0x1F,
+            ..Default::default()
+        };
+        assert_eq!(state.to_bytes()[6], 0b1111_1100);
+    }
+
+    #[test]
+    fn keyboard_report_is_modifiers_reserved_and_six_keys() {
+        let mut state = ControllerState::default();
+        for c in ['H', 'i', '!'] {
+            state.keyboard.press(KeyPress::from_char(c).unwrap());
+        }
+        assert_eq!(
+            state.keyboard_report(),
+            [0x02, 0x00, 0x0B, 0x0C, 0x1E, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(ControllerState::default().keyboard_report(), [0; 8]);
+    }
 }
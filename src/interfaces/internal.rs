@@ -1,7 +1,16 @@
+use std::{error, fmt, time::Duration};
+
 use bitflags::bitflags;
+use serde::Deserialize;
+
+use crate::profile::{
+    dpad::DpadSettings, filter::FilterSettings, gyro::GyroSettings, mouse::MouseSettings,
+    stick::StickSettings, tilt::TiltMapping, touch::TouchMapping, trigger::TriggerSettings,
+    turbo::Turbo,
+};
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct Buttons: u32 {
         const SQUARE    = 1 << 0;
         const CROSS     = 1 << 1;
@@ -37,95 +46,607 @@ bitflags! {
     }
 }
 
-#[derive(Debug)]
+bitflags! {
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct MouseButtons: u8 {
+        const LEFT   = 1 << 0;
+        const RIGHT  = 1 << 1;
+        const MIDDLE = 1 << 2;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct KeyModifiers: u8 {
+        const LEFT_CTRL   = 1 << 0;
+        const LEFT_SHIFT  = 1 << 1;
+        const LEFT_ALT    = 1 << 2;
+        const LEFT_GUI    = 1 << 3;
+        const RIGHT_CTRL  = 1 << 4;
+        const RIGHT_SHIFT = 1 << 5;
+        const RIGHT_ALT   = 1 << 6;
+        const RIGHT_GUI   = 1 << 7;
+    }
+}
+
+/// A key from the HID Keyboard/Keypad usage page, with the modifiers held along with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyPress {
+    pub usage: u8,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyPress {
+    /// The key that types `c` on a US layout.
+    pub fn from_char(c: char) -> Option<Self> {
+        const SHIFTED_DIGITS: &str = "!@#$%^&*()";
+        const PUNCTUATION: &[(char, char, u8)] = &[
+            ('-', '_', 0x2D),
+            ('=', '+', 0x2E),
+            ('[', '{', 0x2F),
+            (']', '}', 0x30),
+            ('\\', '|', 0x31),
+            (';', ':', 0x33),
+            ('\'', '"', 0x34),
+            ('`', '~', 0x35),
+            (',', '<', 0x36),
+            ('.', '>', 0x37),
+            ('/', '?', 0x38),
+        ];
+
+        let plain = |usage| Self {
+            usage,
+            modifiers: KeyModifiers::empty(),
+        };
+        let shifted = |usage| Self {
+            usage,
+            modifiers: KeyModifiers::LEFT_SHIFT,
+        };
+        Some(match c {
+            'a'..='z' => plain(0x04 + (c as u8 - b'a')),
+            'A'..='Z' => shifted(0x04 + (c as u8 - b'A')),
+            '1'..='9' => plain(0x1E + (c as u8 - b'1')),
+            '0' => plain(0x27),
+            '\n' => plain(0x28),
+            '\t' => plain(0x2B),
+            ' ' => plain(0x2C),
+            _ => {
+                if let Some(i) = SHIFTED_DIGITS.find(c) {
+                    shifted(0x1E + i as u8)
+                } else {
+                    let &(base, _, usage) = PUNCTUATION
+                        .iter()
+                        .find(|(base, shift, _)| c == *base || c == *shift)?;
+                    if c == base {
+                        plain(usage)
+                    } else {
+                        shifted(usage)
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Keys held in one frame; the keyboard report has room for six at a time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Keyboard {
+    pub modifiers: KeyModifiers,
+    pub keys: Vec<u8>,
+}
+
+impl Keyboard {
+    pub const MAX_KEYS: usize = 6;
+
+    pub fn press(&mut self, key: KeyPress) {
+        self.modifiers |= key.modifiers;
+        if !self.keys.contains(&key.usage) && self.keys.len() < Self::MAX_KEYS {
+            self.keys.push(key.usage);
+        }
+    }
+
+    pub fn release(&mut self, key: KeyPress) {
+        self.modifiers -= key.modifiers;
+        self.keys.retain(|usage| *usage != key.usage);
+    }
+
+    pub fn merge(&mut self, other: &Keyboard) {
+        self.modifiers |= other.modifiers;
+        for usage in &other.keys {
+            self.press(KeyPress {
+                usage: *usage,
+                modifiers: KeyModifiers::empty(),
+            });
+        }
+    }
+}
+
+/// Mouse output of one frame, the movement is relative to the previous frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mouse {
+    pub buttons: MouseButtons,
+    pub dx: i32,
+    pub dy: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Axis2D {
     pub x: u8,
     pub y: u8,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Axis3D {
     pub x: i16,
     pub y: i16,
     pub z: i16,
 }
 
+/// Calibrated motion sensors, gyro in deg/s (pitch, yaw, roll) and accelerometer in g.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Motion {
+    pub gyro: [f32; 3],
+    pub accel: [f32; 3],
+}
+
+/// Tick rate and width of a controller's sensor timestamp (`ControllerStateInternal::ts`).
+#[derive(Debug, Clone, Copy)]
+pub struct SensorClock {
+    pub ticks_per_second: u32,
+    /// The counter wraps around after this many bits.
+    pub bits: u32,
+}
+
+impl SensorClock {
+    /// Time from `earlier` to `later`, assuming the counter wrapped at most once in between.
+    pub fn seconds_between(&self, earlier: u32, later: u32) -> f32 {
+        let mask = u32::MAX >> (32 - self.bits);
+        let ticks = later.wrapping_sub(earlier) & mask;
+        ticks as f32 / self.ticks_per_second as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TouchPoint {
+    pub id: u8,
+    pub x: u16, // 0 - 1919
+    pub y: u16, // 0 - 1079 (DualSense), 0 - 941 (DualShock 4)
+}
+
+#[derive(Debug, Default)]
+pub struct Touchpad {
+    pub fingers: [Option<TouchPoint>; 2],
+}
+
 #[derive(Debug)]
 pub struct ControllerStateInternal {
     pub l: Axis2D,
     pub r: Axis2D,
     pub gyro: Axis3D,
     pub accel: Axis3D,
+    pub motion: Motion,
     pub battery: u8,
     pub power_state: PowerState,
     pub ts: u32,
     pub button: Buttons,
     pub l2_axis: u8,
     pub r2_axis: u8,
+    pub touchpad: Touchpad,
+    pub mouse: Mouse,
+    pub keyboard: Keyboard,
+    pub turbo: Vec<TurboSlot>,
+}
+
+/// Buttons to auto-fire in the outgoing reports: held for `press_ticks`, then released for
+/// `release_ticks`.
+#[derive(Debug, Clone, Copy)]
+pub struct TurboSlot {
+    pub buttons: Buttons,
+    pub press_ticks: u8,
+    pub release_ticks: u8,
 }
 
 pub struct Profile {
     pub name: String,
     pub sensitivity: String,
+    pub left_stick: StickSettings,
+    pub right_stick: StickSettings,
+    pub l2: TriggerSettings,
+    pub r2: TriggerSettings,
+    pub turbo: Vec<Turbo>,
     pub macros: Vec<Macro>,
+    pub layers: Vec<Layer>,
+    pub gyro: Option<GyroSettings>,
+    pub tilt: Vec<TiltMapping>,
+    pub touch: Vec<TouchMapping>,
+    pub mouse: Option<MouseSettings>,
+    pub dpad: DpadSettings,
+    pub filter: FilterSettings,
+}
+
+/// Extra mapping stacked on top of the profile while its shift chord is held.
+///
+/// The shift buttons are consumed: they are not sent to the host and no macro sees them. A
+/// layer whose chord is part of a bigger held chord yields to that layer, other held layers
+/// all stay active. Masks run as one pipeline, profile macros first and then each active
+/// layer in the order they are defined, so a later `filter`/`add` overrides an earlier one.
+pub struct Layer {
+    pub name: String,
+    pub shift: Buttons,
+    /// Replaces the profile sensitivity while the layer is active.
+    pub sensitivity: Option<String>,
+    pub macros: Vec<Macro>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MacroMode {
+    /// Active while the trigger combo is held.
+    #[default]
+    Momentary,
+    /// Each press of the trigger combo flips the macro on or off.
+    Toggle,
+    /// Active once the trigger combo has been held for the given time, until it is released.
+    /// The `filter` buttons are held back while the press is pending, so with the trigger
+    /// buttons filtered a short press sends nothing.
+    LongPress(Duration),
 }
 
 pub struct Macro {
     //input
-    include: Buttons,
-    exclude: Buttons,
+    pub include: Buttons,
+    pub exclude: Buttons,
+    pub mode: MacroMode,
     //output
-    filter: Buttons,
-    add: Buttons,
-    switch_profile: Option<String>,
-    hold_sensitivity: Option<String>,
-    macro_list: Option<Vec<MacroAction>>,
+    pub filter: Buttons,
+    pub add: Buttons,
+    pub switch_profile: Option<String>,
+    pub hold_sensitivity: Option<String>,
+    /// Keyboard key held while the macro is active, e.g. for push-to-talk.
+    pub key: Option<KeyPress>,
+    pub macro_list: Option<Vec<MacroAction>>,
 }
 
-enum MacroAction {
-    PressJoystick { left: Axis2D, right: Axis2D },
+impl Macro {
+    /// A macro fires while all `include` buttons and none of the `exclude` buttons are held.
+    pub fn is_triggered(&self, buttons: Buttons) -> bool {
+        !self.include.is_empty()
+            && buttons.contains(self.include)
+            && !buttons.intersects(self.exclude)
+    }
+
+    /// Removes the `filter` buttons and presses the `add` buttons.
+    pub fn apply(&self, buttons: Buttons) -> Buttons {
+        (buttons - self.filter) | self.add
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MacroAction {
+    PressJoystick {
+        left: Axis2D,
+        right: Axis2D,
+    },
     Press(Buttons),
-    ReleaseJoystick { left: Axis2D, right: Axis2D },
+    ReleaseJoystick,
     Release(Buttons),
     Sleep(u64),
+    /// Presses and releases a keyboard key.
+    Key {
+        usage: u8,
+        modifiers: KeyModifiers,
+    },
+    /// Types the text key by key on a US layout.
+    TypeText(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// Fritsch-Carlson monotone cubic, smooth without overshooting between points.
+    MonotoneCubic,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CurveError {
+    Unsorted { x: u8 },
+    Duplicate { x: u8 },
+    MissingStart,
+    MissingEnd,
 }
 
+impl fmt::Display for CurveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CurveError::Unsorted { x } => write!(f, "curve points are not sorted (at x = {})", x),
+            CurveError::Duplicate { x } => write!(f, "curve has more than one point at x = {}", x),
+            CurveError::MissingStart => write!(f, "curve must start at x = 0"),
+            CurveError::MissingEnd => write!(f, "curve must end at x = 255"),
+        }
+    }
+}
+
+impl error::Error for CurveError {}
+
 pub struct SensitivityProfile {
     pub name: String,
-    pub curve: Vec<Point>,
+    curve: Vec<Point>,
+    // Every input value resolved once up front, so the per-frame lookup is a plain index.
+    lut: [u8; 256],
 }
 
 impl SensitivityProfile {
+    /// Builds the curve, which must be sorted by x, unique, and cover both 0 and 255.
+    pub fn new(
+        name: String,
+        curve: Vec<Point>,
+        interpolation: Interpolation,
+    ) -> Result<Self, CurveError> {
+        for window in curve.windows(2) {
+            if window[1].x == window[0].x {
+                return Err(CurveError::Duplicate { x: window[1].x });
+            }
+            if window[1].x < window[0].x {
+                return Err(CurveError::Unsorted { x: window[1].x });
+            }
+        }
+        if curve.first().is_none_or(|p| p.x != 0) {
+            return Err(CurveError::MissingStart);
+        }
+        if curve.last().is_none_or(|p| p.x != 255) {
+            return Err(CurveError::MissingEnd);
+        }
+
+        let lut = match interpolation {
+            Interpolation::Linear => linear_lut(&curve),
+            Interpolation::MonotoneCubic => monotone_cubic_lut(&curve),
+        };
+        Ok(Self { name, curve, lut })
+    }
+
+    pub fn curve(&self) -> &[Point] {
+        &self.curve
+    }
+
     pub fn get(&self, x: u8) -> Point {
-        if let Some(p) = self.curve.iter().find(|p| p.x == x) {
-            return *p;
+        Point {
+            x,
+            y: self.lut[x as usize],
         }
+    }
+}
+
+fn to_u8(y: f64) -> u8 {
+    y.round().clamp(0.0, 255.0) as u8
+}
 
-        // should never happen
-        if x < self.curve.first().unwrap().x || x > self.curve.last().unwrap().x {
-            return Point { x, y: x };
+fn linear_lut(curve: &[Point]) -> [u8; 256] {
+    let mut lut = [0u8; 256];
+    for window in curve.windows(2) {
+        let (a, b) = (window[0], window[1]);
+        let m = (b.y as f64 - a.y as f64) / (b.x as f64 - a.x as f64);
+        for x in a.x..=b.x {
+            lut[x as usize] = to_u8(a.y as f64 + m * (x - a.x) as f64);
         }
+    }
+    lut
+}
 
-        for window in self.curve.windows(2) {
-            let a = window[0];
-            let b = window[1];
-            if x > a.x && x < b.x {
-                let m = (b.y as f64 - a.y as f64) / (b.x as f64 - a.x as f64);
-                let y = a.y as f64 + m * (x as f64 - a.x as f64);
-                return Point {
-                    x,
-                    y: y.round() as u8,
-                };
-            }
+fn monotone_cubic_lut(curve: &[Point]) -> [u8; 256] {
+    let xs = curve.iter().map(|p| p.x as f64).collect::<Vec<_>>();
+    let ys = curve.iter().map(|p| p.y as f64).collect::<Vec<_>>();
+    let n = curve.len();
+
+    let secants = (0..n - 1)
+        .map(|k| (ys[k + 1] - ys[k]) / (xs[k + 1] - xs[k]))
+        .collect::<Vec<_>>();
+
+    let mut tangents = vec![0.0; n];
+    tangents[0] = secants[0];
+    tangents[n - 1] = secants[n - 2];
+    for k in 1..n - 1 {
+        if secants[k - 1] * secants[k] > 0.0 {
+            tangents[k] = (secants[k - 1] + secants[k]) / 2.0;
         }
+    }
+    for k in 0..n - 1 {
+        if secants[k] == 0.0 {
+            tangents[k] = 0.0;
+            tangents[k + 1] = 0.0;
+            continue;
+        }
+        let a = tangents[k] / secants[k];
+        let b = tangents[k + 1] / secants[k];
+        let h = a * a + b * b;
+        if h > 9.0 {
+            let t = 3.0 / h.sqrt();
+            tangents[k] = t * a * secants[k];
+            tangents[k + 1] = t * b * secants[k];
+        }
+    }
 
-        // should never happen
-        Point { x, y: x }
+    let mut lut = [0u8; 256];
+    for k in 0..n - 1 {
+        let h = xs[k + 1] - xs[k];
+        for x in curve[k].x..=curve[k + 1].x {
+            let t = (x as f64 - xs[k]) / h;
+            let (t2, t3) = (t * t, t * t * t);
+            let y = (2.0 * t3 - 3.0 * t2 + 1.0) * ys[k]
+                + (t3 - 2.0 * t2 + t) * h * tangents[k]
+                + (-2.0 * t3 + 3.0 * t2) * ys[k + 1]
+                + (t3 - t2) * h * tangents[k + 1];
+            lut[x as usize] = to_u8(y);
+        }
     }
+    lut
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Point {
     pub x: u8,
     pub y: u8,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(points: &[(u8, u8)]) -> Vec<Point> {
+        points.iter().map(|&(x, y)| Point { x, y }).collect()
+    }
+
+    fn profile(curve: &[(u8, u8)], interpolation: Interpolation) -> SensitivityProfile {
+        SensitivityProfile::new("test".to_string(), points(curve), interpolation).unwrap()
+    }
+
+    fn error(curve: &[(u8, u8)]) -> CurveError {
+        SensitivityProfile::new("test".to_string(), points(curve), Interpolation::Linear)
+            .err()
+            .unwrap()
+    }
+
+    #[test]
+    fn rejects_invalid_curves() {
+        assert_eq!(error(&[]), CurveError::MissingStart);
+        assert_eq!(error(&[(0, 0)]), CurveError::MissingEnd);
+        assert_eq!(error(&[(1, 0), (255, 255)]), CurveError::MissingStart);
+        assert_eq!(error(&[(0, 0), (254, 255)]), CurveError::MissingEnd);
+        assert_eq!(
+            error(&[(0, 0), (200, 10), (100, 50), (255, 255)]),
+            CurveError::Unsorted { x: 100 }
+        );
+        assert_eq!(
+            error(&[(0, 0), (100, 10), (100, 50), (255, 255)]),
+            CurveError::Duplicate { x: 100 }
+        );
+    }
+
+    #[test]
+    fn linear_hits_points_and_interpolates() {
+        let curve = profile(&[(0, 0), (128, 64), (255, 255)], Interpolation::Linear);
+        assert_eq!(curve.get(0).y, 0);
+        assert_eq!(curve.get(64).y, 32);
+        assert_eq!(curve.get(128).y, 64);
+        assert_eq!(curve.get(255).y, 255);
+        assert_eq!(curve.get(200).x, 200);
+    }
+
+    #[test]
+    fn identity_curve_is_identity() {
+        for interpolation in [Interpolation::Linear, Interpolation::MonotoneCubic] {
+            let curve = profile(&[(0, 0), (255, 255)], interpolation);
+            for x in 0..=255u8 {
+                assert_eq!(curve.get(x).y, x);
+            }
+        }
+    }
+
+    #[test]
+    fn decreasing_curve_is_supported() {
+        let curve = profile(&[(0, 255), (255, 0)], Interpolation::Linear);
+        assert_eq!(curve.get(0).y, 255);
+        assert_eq!(curve.get(255).y, 0);
+    }
+
+    #[test]
+    fn monotone_cubic_hits_points_without_overshoot() {
+        let curve = profile(
+            &[(0, 0), (100, 10), (110, 240), (255, 255)],
+            Interpolation::MonotoneCubic,
+        );
+        assert_eq!(curve.get(0).y, 0);
+        assert_eq!(curve.get(100).y, 10);
+        assert_eq!(curve.get(110).y, 240);
+        assert_eq!(curve.get(255).y, 255);
+        for x in 0..255u8 {
+            assert!(
+                curve.get(x).y <= curve.get(x + 1).y,
+                "not monotone at {}",
+                x
+            );
+        }
+    }
+
+    #[test]
+    fn flat_segments_stay_flat() {
+        let curve = profile(
+            &[(0, 0), (50, 100), (150, 100), (255, 255)],
+            Interpolation::MonotoneCubic,
+        );
+        for x in 50..=150u8 {
+            assert_eq!(curve.get(x).y, 100);
+        }
+    }
+
+    #[test]
+    fn sensor_clock_wraps_at_its_width() {
+        let ds4 = SensorClock {
+            ticks_per_second: 187_500,
+            bits: 16,
+        };
+        assert_eq!(ds4.seconds_between(1000, 1000 + 1875), 0.01);
+        // The 16-bit counter wraps from 0xFFFF to 0; upper bits must not leak in.
+        assert_eq!(ds4.seconds_between(0xFFFF - 999, 875), 0.01);
+        assert_eq!(ds4.seconds_between(0x1_FFFF - 999, 0x2_0000 + 875), 0.01);
+
+        let dualsense = SensorClock {
+            ticks_per_second: 3_000_000,
+            bits: 32,
+        };
+        assert_eq!(dualsense.seconds_between(1_000_000, 1_030_000), 0.01);
+        assert_eq!(dualsense.seconds_between(u32::MAX - 9_999, 20_000), 0.01);
+        assert_eq!(dualsense.seconds_between(5, 5), 0.0);
+    }
+
+    #[test]
+    fn characters_map_to_us_layout_keys() {
+        let shift = KeyModifiers::LEFT_SHIFT;
+        let none = KeyModifiers::empty();
+        let cases = [
+            ('a', 0x04, none),
+            ('z', 0x1D, none),
+            ('A', 0x04, shift),
+            ('1', 0x1E, none),
+            ('9', 0x26, none),
+            ('0', 0x27, none),
+            ('!', 0x1E, shift),
+            ('@', 0x1F, shift),
+            ('(', 0x26, shift),
+            (')', 0x27, shift),
+            ('-', 0x2D, none),
+            ('_', 0x2D, shift),
+            ('[', 0x2F, none),
+            ('{', 0x2F, shift),
+            ('\\', 0x31, none),
+            ('|', 0x31, shift),
+            ('\'', 0x34, none),
+            ('"', 0x34, shift),
+            ('/', 0x38, none),
+            ('?', 0x38, shift),
+            ('\n', 0x28, none),
+            ('\t', 0x2B, none),
+            (' ', 0x2C, none),
+        ];
+        for (c, usage, modifiers) in cases {
+            assert_eq!(
+                KeyPress::from_char(c),
+                Some(KeyPress { usage, modifiers }),
+                "{:?}",
+                c
+            );
+        }
+        assert_eq!(KeyPress::from_char('é'), None);
+    }
+
+    #[test]
+    fn keyboard_holds_at_most_six_keys() {
+        let mut keyboard = Keyboard::default();
+        for c in "abcabcdefgH".chars() {
+            keyboard.press(KeyPress::from_char(c).unwrap());
+        }
+        assert_eq!(keyboard.keys, [0x04, 0x05, 0x06, 0x07, 0x08, 0x09]);
+        // Modifiers are not keys and still come through.
+        assert_eq!(keyboard.modifiers, KeyModifiers::LEFT_SHIFT);
+    }
+}
@@ -1,8 +1,12 @@
-use std::fmt;
+use std::{error, fmt};
 
 use bitflags::bitflags;
+use hidapi::{BusType, HidDevice, HidError};
 
-use super::internal::{Axis2D, Axis3D, Buttons, ControllerStateInternal, PowerState};
+use super::internal::{
+    Axis2D, Axis3D, Buttons, ControllerStateInternal, Keyboard, Motion, Mouse, PowerState,
+    SensorClock, TouchPoint, Touchpad,
+};
 
 bitflags! {
     #[derive(Debug, Clone, Copy)]
@@ -53,6 +57,399 @@ pub enum HatDirection {
     NorthWest = 0x07,
 }
 
+pub const SONY_VENDOR_ID: u16 = 0x054C;
+
+pub const DS4_USB_REPORT_ID: u8 = 0x01;
+pub const DS4_USB_REPORT_LEN: usize = 64;
+pub const DS4_BT_REPORT_ID: u8 = 0x11;
+pub const DS4_BT_REPORT_LEN: usize = 78;
+pub const DS4_BT_SHORT_REPORT_ID: u8 = 0x01;
+pub const DS4_BT_SHORT_REPORT_LEN: usize = 10;
+pub const DS_USB_REPORT_ID: u8 = 0x01;
+pub const DS_USB_REPORT_LEN: usize = 64;
+pub const DS_BT_REPORT_ID: u8 = 0x31;
+pub const DS_BT_REPORT_LEN: usize = 78;
+pub const DS_BT_SHORT_REPORT_ID: u8 = 0x01;
+pub const DS_BT_SHORT_REPORT_LEN: usize = 10;
+
+pub const DS4_USB_CALIBRATION_REPORT_ID: u8 = 0x02;
+pub const DS4_USB_CALIBRATION_REPORT_LEN: usize = 37;
+pub const DS4_BT_CALIBRATION_REPORT_ID: u8 = 0x05;
+pub const DS4_BT_CALIBRATION_REPORT_LEN: usize = 41;
+pub const DS_CALIBRATION_REPORT_ID: u8 = 0x05;
+pub const DS_CALIBRATION_REPORT_LEN: usize = 41;
+
+// Calibrated sensor values are normalized to these steps, as in hid-playstation.
+const GYRO_RES_PER_DEG_S: i32 = 1024;
+const ACC_RES_PER_G: i32 = 8192;
+// Full scale of the uncalibrated sensors, used when the calibration data is unusable.
+const GYRO_RANGE: i32 = 2048 * GYRO_RES_PER_DEG_S;
+const ACC_RANGE: i32 = 4 * ACC_RES_PER_G;
+
+// Bluetooth input reports are checksummed over the HID transaction header (DATA | INPUT)
+// followed by the report itself.
+const BT_INPUT_CRC_SEED: u8 = 0xA1;
+// Bluetooth feature reports the same way, with the DATA | FEATURE header.
+const BT_FEATURE_CRC_SEED: u8 = 0xA3;
+
+#[derive(Debug)]
+pub enum ParseError {
+    ReportId { expected: u8, found: u8 },
+    Length { expected: usize, found: usize },
+    Crc { expected: u32, found: u32 },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::ReportId { expected, found } => write!(
+                f,
+                "unexpected report id 0x{:02x} (expected 0x{:02x})",
+                found, expected
+            ),
+            ParseError::Length { expected, found } => write!(
+                f,
+                "unexpected report length {} (expected {})",
+                found, expected
+            ),
+            ParseError::Crc { expected, found } => write!(
+                f,
+                "crc mismatch 0x{:08x} (expected 0x{:08x})",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+#[derive(Debug)]
+pub enum CalibrationError {
+    Hid(HidError),
+    Report(ParseError),
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::Hid(e) => write!(f, "failed to read calibration: {}", e),
+            CalibrationError::Report(e) => write!(f, "invalid calibration report: {}", e),
+        }
+    }
+}
+
+impl error::Error for CalibrationError {}
+
+impl From<HidError> for CalibrationError {
+    fn from(value: HidError) -> Self {
+        CalibrationError::Hid(value)
+    }
+}
+
+impl From<ParseError> for CalibrationError {
+    fn from(value: ParseError) -> Self {
+        CalibrationError::Report(value)
+    }
+}
+
+/// The controller families we know how to parse, picked from the USB VID/PID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputDevice {
+    DualShock4,
+    DualSense,
+}
+
+impl InputDevice {
+    pub fn from_vid_pid(vendor_id: u16, product_id: u16) -> Option<Self> {
+        if vendor_id != SONY_VENDOR_ID {
+            return None;
+        }
+        match product_id {
+            0x05C4 | 0x09CC | 0x0BA0 => Some(InputDevice::DualShock4),
+            0x0CE6 | 0x0DF2 => Some(InputDevice::DualSense),
+            _ => None,
+        }
+    }
+
+    /// DualShock 4 counts in 16-bit steps of 16/3 µs, DualSense in 32-bit steps of 1/3 µs.
+    pub fn sensor_clock(&self) -> SensorClock {
+        match self {
+            InputDevice::DualShock4 => SensorClock {
+                ticks_per_second: 187_500,
+                bits: 16,
+            },
+            InputDevice::DualSense => SensorClock {
+                ticks_per_second: 3_000_000,
+                bits: 32,
+            },
+        }
+    }
+
+    /// Touchpad resolution, the y range differs between the two controllers.
+    pub fn touchpad_size(&self) -> (u16, u16) {
+        match self {
+            InputDevice::DualShock4 => (1920, 942),
+            InputDevice::DualSense => (1920, 1080),
+        }
+    }
+
+    /// Reads the IMU calibration from the controller's feature report.
+    ///
+    /// The DualShock 4 only answers 0x02 over USB; over Bluetooth the same data comes as 0x05
+    /// with the gyro ranges in a different order. Reading 0x05 also switches a Bluetooth
+    /// DualShock 4 to the full input report. Reports read over Bluetooth end in a CRC.
+    pub fn read_calibration(&self, device: &HidDevice) -> Result<ImuCalibration, CalibrationError> {
+        match self {
+            InputDevice::DualShock4 => {
+                let mut buf = [0u8; DS4_USB_CALIBRATION_REPORT_LEN];
+                buf[0] = DS4_USB_CALIBRATION_REPORT_ID;
+                if let Ok(len) = device.get_feature_report(&mut buf) {
+                    return Ok(ImuCalibration::from_ds4_usb_report(&buf[..len])?);
+                }
+                let mut buf = [0u8; DS4_BT_CALIBRATION_REPORT_LEN];
+                buf[0] = DS4_BT_CALIBRATION_REPORT_ID;
+                let len = device.get_feature_report(&mut buf)?;
+                Ok(ImuCalibration::from_ds4_bt_report(&buf[..len])?)
+            }
+            InputDevice::DualSense => {
+                let mut buf = [0u8; DS_CALIBRATION_REPORT_LEN];
+                buf[0] = DS_CALIBRATION_REPORT_ID;
+                let len = device.get_feature_report(&mut buf)?;
+                let bluetooth = device
+                    .get_device_info()
+                    .is_ok_and(|info| matches!(info.bus_type(), BusType::Bluetooth));
+                Ok(ImuCalibration::from_ds_report(&buf[..len], bluetooth)?)
+            }
+        }
+    }
+
+    pub fn parse(&self, buf: &[u8]) -> Result<ParsedInput, ParseError> {
+        match self {
+            InputDevice::DualShock4 if buf.len() == DS4_USB_REPORT_LEN => {
+                ParsedInput::from_ps4_buf(buf)
+            }
+            InputDevice::DualShock4 => ParsedInput::from_ps4_bt_buf(buf),
+            // USB and the reduced Bluetooth report share report id 0x01, only the length differs.
+            InputDevice::DualSense if buf.len() == DS_USB_REPORT_LEN => {
+                ParsedInput::from_ps5_buf(buf)
+            }
+            InputDevice::DualSense => ParsedInput::from_ps5_bt_buf(buf),
+        }
+    }
+}
+
+fn check_report(buf: &[u8], report_id: u8, len: usize) -> Result<(), ParseError> {
+    if buf.len() != len {
+        return Err(ParseError::Length {
+            expected: len,
+            found: buf.len(),
+        });
+    }
+    if buf[0] != report_id {
+        return Err(ParseError::ReportId {
+            expected: report_id,
+            found: buf[0],
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AxisCalibration {
+    bias: i32,
+    sens_numer: i32,
+    sens_denom: i32,
+}
+
+impl AxisCalibration {
+    fn gyro(plus: i16, minus: i16, bias: i16, speed_2x: i32) -> Self {
+        // The bias is applied by the controller itself, it only widens the measured range.
+        let sens_denom = (plus as i32 - bias as i32).abs() + (minus as i32 - bias as i32).abs();
+        if sens_denom == 0 {
+            return Self::uncalibrated(GYRO_RANGE);
+        }
+        Self {
+            bias: 0,
+            sens_numer: speed_2x * GYRO_RES_PER_DEG_S,
+            sens_denom,
+        }
+    }
+
+    fn accel(plus: i16, minus: i16) -> Self {
+        let range_2g = plus as i32 - minus as i32;
+        if range_2g == 0 {
+            return Self::uncalibrated(ACC_RANGE);
+        }
+        Self {
+            bias: plus as i32 - range_2g / 2,
+            sens_numer: 2 * ACC_RES_PER_G,
+            sens_denom: range_2g,
+        }
+    }
+
+    fn uncalibrated(range: i32) -> Self {
+        Self {
+            bias: 0,
+            sens_numer: range,
+            sens_denom: i16::MAX as i32,
+        }
+    }
+
+    // In steps of 1/GYRO_RES_PER_DEG_S deg/s or 1/ACC_RES_PER_G g.
+    fn apply(&self, raw: i16) -> f32 {
+        (raw as i32 - self.bias) as f32 * self.sens_numer as f32 / self.sens_denom as f32
+    }
+}
+
+/// Per-axis gyro and accelerometer calibration, see `InputDevice::read_calibration`.
+///
+/// Follows hid-playstation; the default scales the raw values by the nominal sensor ranges.
+#[derive(Debug, Clone, Copy)]
+pub struct ImuCalibration {
+    gyro: [AxisCalibration; 3],
+    accel: [AxisCalibration; 3],
+}
+
+impl Default for ImuCalibration {
+    fn default() -> Self {
+        Self {
+            gyro: [AxisCalibration::uncalibrated(GYRO_RANGE); 3],
+            accel: [AxisCalibration::uncalibrated(ACC_RANGE); 3],
+        }
+    }
+}
+
+impl ImuCalibration {
+    /// DualSense feature report 0x05, over USB and Bluetooth; only the Bluetooth one has a CRC.
+    pub fn from_ds_report(buf: &[u8], bluetooth: bool) -> Result<Self, ParseError> {
+        check_report(buf, DS_CALIBRATION_REPORT_ID, DS_CALIBRATION_REPORT_LEN)?;
+        if bluetooth {
+            check_crc(BT_FEATURE_CRC_SEED, buf)?;
+        }
+        let v = calibration_values(buf);
+        Ok(Self::new(v, [v[3], v[5], v[7]], [v[4], v[6], v[8]]))
+    }
+
+    /// DualShock 4 feature report 0x02 over USB: plus and minus ranges alternate per axis.
+    pub fn from_ds4_usb_report(buf: &[u8]) -> Result<Self, ParseError> {
+        check_report(
+            buf,
+            DS4_USB_CALIBRATION_REPORT_ID,
+            DS4_USB_CALIBRATION_REPORT_LEN,
+        )?;
+        let v = calibration_values(buf);
+        Ok(Self::new(v, [v[3], v[5], v[7]], [v[4], v[6], v[8]]))
+    }
+
+    /// DualShock 4 feature report 0x05 over Bluetooth: all plus ranges come first.
+    pub fn from_ds4_bt_report(buf: &[u8]) -> Result<Self, ParseError> {
+        check_report(
+            buf,
+            DS4_BT_CALIBRATION_REPORT_ID,
+            DS4_BT_CALIBRATION_REPORT_LEN,
+        )?;
+        check_crc(BT_FEATURE_CRC_SEED, buf)?;
+        let v = calibration_values(buf);
+        Ok(Self::new(v, [v[3], v[4], v[5]], [v[6], v[7], v[8]]))
+    }
+
+    // `v` holds the report's 16-bit values from byte 1 on, starting with the pitch, yaw and roll
+    // bias and ending with the gyro speed and the accelerometer ranges.
+    fn new(v: [i16; 17], gyro_plus: [i16; 3], gyro_minus: [i16; 3]) -> Self {
+        let speed_2x = v[9] as i32 + v[10] as i32;
+        Self {
+            gyro: [0, 1, 2]
+                .map(|i| AxisCalibration::gyro(gyro_plus[i], gyro_minus[i], v[i], speed_2x)),
+            accel: [0, 1, 2].map(|i| AxisCalibration::accel(v[11 + i * 2], v[12 + i * 2])),
+        }
+    }
+
+    /// Fills `state.motion` from the raw `gyro` and `accel` readings.
+    pub fn apply(&self, state: &mut ControllerStateInternal) {
+        let gyro = [state.gyro.x, state.gyro.y, state.gyro.z];
+        let accel = [state.accel.x, state.accel.y, state.accel.z];
+        state.motion = Motion {
+            gyro: [0, 1, 2].map(|i| self.gyro[i].apply(gyro[i]) / GYRO_RES_PER_DEG_S as f32),
+            accel: [0, 1, 2].map(|i| self.accel[i].apply(accel[i]) / ACC_RES_PER_G as f32),
+        };
+    }
+}
+
+fn calibration_values(buf: &[u8]) -> [i16; 17] {
+    let mut values = [0i16; 17];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = i16::from_le_bytes([buf[1 + i * 2], buf[2 + i * 2]]);
+    }
+    values
+}
+
+fn hat_from_bits(bits: u8) -> HatDirection {
+    match bits & 0x0F {
+        0x00 => HatDirection::North,
+        0x01 => HatDirection::NorthEast,
+        0x02 => HatDirection::East,
+        0x03 => HatDirection::SouthEast,
+        0x04 => HatDirection::South,
+        0x05 => HatDirection::SouthWest,
+        0x06 => HatDirection::West,
+        0x07 => HatDirection::NorthWest,
+        _ => HatDirection::Neutral,
+    }
+}
+
+// Each touch point is 4 bytes: bit 7 of the first byte is set while the finger is *not*
+// touching, the lower 7 bits are the finger id, followed by 12-bit X and Y.
+fn touch_point_from_bytes(bytes: &[u8]) -> Option<TouchPoint> {
+    if bytes[0] & 0x80 != 0 {
+        return None;
+    }
+    Some(TouchPoint {
+        id: bytes[0] & 0x7F,
+        x: bytes[1] as u16 | ((bytes[2] as u16 & 0x0F) << 8),
+        y: (bytes[2] as u16 >> 4) | ((bytes[3] as u16) << 4),
+    })
+}
+
+// The DualShock 4 status byte has the charge in the low nibble and the cable in bit 4; with
+// the cable in, 11 means fully charged and higher values are errors. Translated to the
+// DualSense layout, following hid-playstation.
+fn ds4_battery(status: u8) -> u8 {
+    let level = status & 0x0F;
+    let cable = status & 0x10 != 0;
+    let (level, power_state) = match (cable, level) {
+        (false, _) => (level.min(10), PowerState::Discharging),
+        (true, 0..=10) => (level, PowerState::Charging),
+        (true, 11) => (10, PowerState::Complete),
+        (true, _) => (0, PowerState::ChargingError),
+    };
+    level | (power_state.bits() << 4)
+}
+
+fn crc32(seed: u8, data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in std::iter::once(&seed).chain(data) {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn check_crc(seed: u8, buf: &[u8]) -> Result<(), ParseError> {
+    let (data, trailer) = buf.split_at(buf.len() - 4);
+    let found = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let expected = crc32(seed, data);
+    if found != expected {
+        return Err(ParseError::Crc { expected, found });
+    }
+    Ok(())
+}
+
 pub struct ParsedInput {
     pub report_id: u8,
     pub lx: u8,
@@ -65,6 +462,8 @@ pub struct ParsedInput {
     pub face_buttons: FaceButtons,
     pub shoulder_buttons: ShoulderButtons,
     pub system_buttons: SystemButtons,
+    /// Charge (0 - 10) in the low nibble, `PowerState` in the high nibble, as the DualSense
+    /// sends it.
     pub battery_level: u8,
     pub ts: u32,
     pub gx: i16,
@@ -73,6 +472,7 @@ pub struct ParsedInput {
     pub ax: i16,
     pub ay: i16,
     pub az: i16,
+    pub touch_points: [Option<TouchPoint>; 2],
 }
 
 impl fmt::Debug for ParsedInput {
@@ -89,25 +489,68 @@ impl fmt::Debug for ParsedInput {
             .field("face_buttons", &self.face_buttons)
             .field("shoulder_buttons", &self.shoulder_buttons)
             .field("system_buttons", &self.system_buttons)
+            .field("touch_points", &self.touch_points)
             // vendor_0x20, vendor_0x21, vendor_0x22 intentionally omitted
             .finish()
     }
 }
 
 impl ParsedInput {
-    pub fn from_ps4_buf(buf: &[u8; 64]) -> Self {
-        let hat = match buf[5] & 0x0F {
-            0x00 => HatDirection::North,
-            0x01 => HatDirection::NorthEast,
-            0x02 => HatDirection::East,
-            0x03 => HatDirection::SouthEast,
-            0x04 => HatDirection::South,
-            0x05 => HatDirection::SouthWest,
-            0x06 => HatDirection::West,
-            0x07 => HatDirection::NorthWest,
-            _ => HatDirection::Neutral,
-        };
+    pub fn from_ps4_buf(buf: &[u8]) -> Result<Self, ParseError> {
+        check_report(buf, DS4_USB_REPORT_ID, DS4_USB_REPORT_LEN)?;
+        Ok(Self::parse_ps4_common(buf))
+    }
+
+    pub fn from_ps5_buf(buf: &[u8]) -> Result<Self, ParseError> {
+        check_report(buf, DS_USB_REPORT_ID, DS_USB_REPORT_LEN)?;
+        Ok(Self::parse_ps5_common(buf))
+    }
 
+    /// Parses a DualShock 4 report received over Bluetooth.
+    ///
+    /// The controller starts out sending the reduced report 0x01 and switches to the full
+    /// report 0x11 once a feature report (e.g. calibration 0x05) has been requested. The full
+    /// report has the USB layout shifted by a two byte header, and a CRC32 trailer.
+    pub fn from_ps4_bt_buf(buf: &[u8]) -> Result<Self, ParseError> {
+        match buf.first() {
+            Some(&DS4_BT_REPORT_ID) => {
+                check_report(buf, DS4_BT_REPORT_ID, DS4_BT_REPORT_LEN)?;
+                check_crc(BT_INPUT_CRC_SEED, buf)?;
+                let mut parsed = Self::parse_ps4_common(&buf[2..]);
+                parsed.report_id = DS4_BT_REPORT_ID;
+                Ok(parsed)
+            }
+            _ => {
+                check_report(buf, DS4_BT_SHORT_REPORT_ID, DS4_BT_SHORT_REPORT_LEN)?;
+                Ok(Self::parse_short(buf))
+            }
+        }
+    }
+
+    /// Parses a DualSense report received over Bluetooth.
+    ///
+    /// Until the controller is switched into full mode (e.g. by reading feature report 0x05)
+    /// it only sends the reduced report 0x01, which carries sticks, buttons and triggers.
+    /// The full report 0x31 has the USB layout shifted by one byte for the sequence tag,
+    /// and a CRC32 trailer.
+    pub fn from_ps5_bt_buf(buf: &[u8]) -> Result<Self, ParseError> {
+        match buf.first() {
+            Some(&DS_BT_REPORT_ID) => {
+                check_report(buf, DS_BT_REPORT_ID, DS_BT_REPORT_LEN)?;
+                check_crc(BT_INPUT_CRC_SEED, buf)?;
+                let mut parsed = Self::parse_ps5_common(&buf[1..]);
+                parsed.report_id = DS_BT_REPORT_ID;
+                Ok(parsed)
+            }
+            _ => {
+                check_report(buf, DS_BT_SHORT_REPORT_ID, DS_BT_SHORT_REPORT_LEN)?;
+                Ok(Self::parse_short(buf))
+            }
+        }
+    }
+
+    // `buf` uses the USB layout: byte 0 is the report id, the payload starts at byte 1.
+    fn parse_ps4_common(buf: &[u8]) -> Self {
         ParsedInput {
             report_id: buf[0],
             lx: buf[1],
@@ -116,34 +559,27 @@ impl ParsedInput {
             ry: buf[4],
             l2_axis: buf[8],
             r2_axis: buf[9],
-            hat,
+            hat: hat_from_bits(buf[5]),
             face_buttons: FaceButtons::from_bits_truncate(buf[5]),
             shoulder_buttons: ShoulderButtons::from_bits_truncate(buf[6]),
             system_buttons: SystemButtons::from_bits_truncate(buf[7] & !SystemButtons::MUTE.bits()),
             ts: u16::from_le_bytes([buf[10], buf[11]]) as u32,
-            battery_level: buf[12],
+            battery_level: ds4_battery(buf[30]),
             gx: i16::from_le_bytes([buf[13], buf[14]]),
             gy: i16::from_le_bytes([buf[15], buf[16]]),
             gz: i16::from_le_bytes([buf[17], buf[18]]),
             ax: i16::from_le_bytes([buf[19], buf[20]]),
             ay: i16::from_le_bytes([buf[21], buf[22]]),
             az: i16::from_le_bytes([buf[23], buf[24]]),
+            touch_points: [
+                touch_point_from_bytes(&buf[35..39]),
+                touch_point_from_bytes(&buf[39..43]),
+            ],
         }
     }
 
-    pub fn from_ps5_buf(buf: &[u8; 64]) -> Self {
-        let hat = match buf[8] & 0x0F {
-            0x00 => HatDirection::North,
-            0x01 => HatDirection::NorthEast,
-            0x02 => HatDirection::East,
-            0x03 => HatDirection::SouthEast,
-            0x04 => HatDirection::South,
-            0x05 => HatDirection::SouthWest,
-            0x06 => HatDirection::West,
-            0x07 => HatDirection::NorthWest,
-            _ => HatDirection::Neutral,
-        };
-
+    // `buf` uses the USB layout: byte 0 is the report id, the payload starts at byte 1.
+    fn parse_ps5_common(buf: &[u8]) -> Self {
         ParsedInput {
             report_id: buf[0],
             lx: buf[1],
@@ -152,7 +588,7 @@ impl ParsedInput {
             ry: buf[4],
             l2_axis: buf[5],
             r2_axis: buf[6],
-            hat,
+            hat: hat_from_bits(buf[8]),
             face_buttons: FaceButtons::from_bits_truncate(buf[8]),
             shoulder_buttons: ShoulderButtons::from_bits_truncate(buf[9]),
             system_buttons: SystemButtons::from_bits_truncate(buf[10]),
@@ -164,13 +600,44 @@ impl ParsedInput {
             ax: i16::from_le_bytes([buf[22], buf[23]]),
             ay: i16::from_le_bytes([buf[24], buf[25]]),
             az: i16::from_le_bytes([buf[26], buf[27]]),
+            touch_points: [
+                touch_point_from_bytes(&buf[33..37]),
+                touch_point_from_bytes(&buf[37..41]),
+            ],
+        }
+    }
+
+    // Reduced Bluetooth report of both controllers: the first ten bytes of a DualShock 4 USB
+    // report, without any sensor data.
+    fn parse_short(buf: &[u8]) -> Self {
+        ParsedInput {
+            report_id: buf[0],
+            lx: buf[1],
+            ly: buf[2],
+            rx: buf[3],
+            ry: buf[4],
+            l2_axis: buf[8],
+            r2_axis: buf[9],
+            hat: hat_from_bits(buf[5]),
+            face_buttons: FaceButtons::from_bits_truncate(buf[5]),
+            shoulder_buttons: ShoulderButtons::from_bits_truncate(buf[6]),
+            system_buttons: SystemButtons::from_bits_truncate(buf[7] & !SystemButtons::MUTE.bits()),
+            battery_level: 0,
+            ts: 0,
+            gx: 0,
+            gy: 0,
+            gz: 0,
+            ax: 0,
+            ay: 0,
+            az: 0,
+            touch_points: [None, None],
         }
     }
 }
 
 impl From<ParsedInput> for ControllerStateInternal {
     fn from(value: ParsedInput) -> Self {
-        ControllerStateInternal {
+        let mut state = ControllerStateInternal {
             l: Axis2D {
                 x: value.lx,
                 y: value.ly,
@@ -197,10 +664,19 @@ impl From<ParsedInput> for ControllerStateInternal {
                 y: value.ay,
                 z: value.az,
             },
+            motion: Motion::default(),
             ts: value.ts,
             battery: value.battery_level & 0x0F,
             power_state: PowerState::from_bits_truncate((value.battery_level >> 4) & 0x0F),
-        }
+            touchpad: Touchpad {
+                fingers: value.touch_points,
+            },
+            mouse: Mouse::default(),
+            keyboard: Keyboard::default(),
+            turbo: Vec::new(),
+        };
+        ImuCalibration::default().apply(&mut state);
+        state
     }
 }
 
@@ -295,3 +771,335 @@ fn convert_all(
         | convert_system_buttons(system)
         | convert_hat_direction(hat)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // CRC32 of 0xA1 followed by the first 74 bytes of `ps5_bt_report`, from zlib.crc32.
+    const PS5_BT_CRC: u32 = 0x3F79_B72C;
+
+    // CRC32 of 0xA1 followed by the first 74 bytes of `ps4_bt_report`, from zlib.crc32.
+    const PS4_BT_CRC: u32 = 0xA4A9_AFA5;
+
+    // Reduced Bluetooth report: South on the hat and CROSS, R1, PS with frame counter 3.
+    const SHORT_REPORT: [u8; 10] = [0x01, 0x12, 0x34, 0x56, 0x78, 0x24, 0x02, 0x0D, 0x9A, 0xBC];
+
+    // DualShock 4 0x11 report: the USB payload shifted by a two byte header.
+    fn ps4_bt_report() -> [u8; 78] {
+        let mut buf = [0u8; 78];
+        buf[0] = 0x11;
+        buf[1] = 0xC0;
+        buf[3..12].copy_from_slice(&SHORT_REPORT[1..]);
+        buf[12..14].copy_from_slice(&0x1234u16.to_le_bytes());
+        buf[15..17].copy_from_slice(&0x0102i16.to_le_bytes());
+        buf[32] = 0x1B;
+        buf[37..41].copy_from_slice(&[0x05, 0x34, 0x12, 0x40]);
+        buf[41] = 0x80;
+        buf[74..].copy_from_slice(&PS4_BT_CRC.to_le_bytes());
+        buf
+    }
+
+    // DualSense 0x31 report: the USB payload shifted by one byte for the sequence tag.
+    fn ps5_bt_report() -> [u8; 78] {
+        let mut buf = [0u8; 78];
+        buf[0] = 0x31;
+        buf[1] = 0x10;
+        buf[2..6].copy_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        buf[6] = 0x9A;
+        buf[7] = 0xBC;
+        // East on the hat, CROSS; L1 and R3; PS.
+        buf[9] = 0x22;
+        buf[10] = 0x81;
+        buf[11] = 0x01;
+        buf[17..19].copy_from_slice(&0x0102i16.to_le_bytes());
+        buf[29..33].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+        buf[34..38].copy_from_slice(&[0x05, 0x34, 0x12, 0x40]);
+        buf[38] = 0x80;
+        buf[54] = 0x28;
+        buf[74..].copy_from_slice(&PS5_BT_CRC.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn crc_uses_the_bluetooth_seed() {
+        let buf = ps5_bt_report();
+        assert_eq!(crc32(BT_INPUT_CRC_SEED, &buf[..74]), PS5_BT_CRC);
+        assert!(check_crc(BT_INPUT_CRC_SEED, &buf).is_ok());
+    }
+
+    #[test]
+    fn corrupted_crc_is_rejected() {
+        let mut buf = ps5_bt_report();
+        buf[75] ^= 0xFF;
+        match ParsedInput::from_ps5_bt_buf(&buf) {
+            Err(ParseError::Crc { expected, found }) => {
+                assert_eq!(expected, PS5_BT_CRC);
+                assert_ne!(found, PS5_BT_CRC);
+            }
+            other => panic!("expected a crc error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn corrupted_payload_is_rejected() {
+        let mut buf = ps5_bt_report();
+        buf[2] ^= 0x01;
+        assert!(matches!(
+            ParsedInput::from_ps5_bt_buf(&buf),
+            Err(ParseError::Crc { .. })
+        ));
+    }
+
+    #[test]
+    fn ps5_bt_fields_are_shifted_by_one() {
+        let parsed = ParsedInput::from_ps5_bt_buf(&ps5_bt_report()).unwrap();
+        assert_eq!(parsed.report_id, DS_BT_REPORT_ID);
+        assert_eq!(
+            (parsed.lx, parsed.ly, parsed.rx, parsed.ry),
+            (0x12, 0x34, 0x56, 0x78)
+        );
+        assert_eq!((parsed.l2_axis, parsed.r2_axis), (0x9A, 0xBC));
+        assert!(matches!(parsed.hat, HatDirection::East));
+        assert_eq!(parsed.face_buttons.bits(), FaceButtons::CROSS.bits());
+        assert_eq!(
+            parsed.shoulder_buttons.bits(),
+            (ShoulderButtons::L1 | ShoulderButtons::R3).bits()
+        );
+        assert_eq!(parsed.system_buttons.bits(), SystemButtons::PS.bits());
+        assert_eq!(parsed.gx, 0x0102);
+        assert_eq!(parsed.ts, 0x1234_5678);
+        assert_eq!(parsed.battery_level, 0x28);
+        let finger = parsed.touch_points[0].unwrap();
+        assert_eq!((finger.id, finger.x, finger.y), (5, 0x234, 0x401));
+        assert!(parsed.touch_points[1].is_none());
+    }
+
+    #[test]
+    fn wrong_length_is_rejected() {
+        let buf = ps5_bt_report();
+        assert!(matches!(
+            ParsedInput::from_ps5_bt_buf(&buf[..77]),
+            Err(ParseError::Length {
+                expected: DS_BT_REPORT_LEN,
+                found: 77
+            })
+        ));
+    }
+
+    #[test]
+    fn ps4_bt_fields_are_shifted_by_two() {
+        let buf = ps4_bt_report();
+        assert_eq!(crc32(BT_INPUT_CRC_SEED, &buf[..74]), PS4_BT_CRC);
+        let parsed = InputDevice::DualShock4.parse(&buf).unwrap();
+        assert_eq!(parsed.report_id, DS4_BT_REPORT_ID);
+        assert_eq!(
+            (parsed.lx, parsed.ly, parsed.rx, parsed.ry),
+            (0x12, 0x34, 0x56, 0x78)
+        );
+        assert_eq!((parsed.l2_axis, parsed.r2_axis), (0x9A, 0xBC));
+        assert!(matches!(parsed.hat, HatDirection::South));
+        assert_eq!(parsed.face_buttons.bits(), FaceButtons::CROSS.bits());
+        assert_eq!(parsed.shoulder_buttons.bits(), ShoulderButtons::R1.bits());
+        // The frame counter shares the byte and must not show up as MUTE.
+        assert_eq!(parsed.system_buttons.bits(), SystemButtons::PS.bits());
+        assert_eq!(parsed.ts, 0x1234);
+        assert_eq!(parsed.gx, 0x0102);
+        // Cable in and 11: fully charged.
+        assert_eq!(parsed.battery_level, 0x2A);
+        let finger = parsed.touch_points[0].unwrap();
+        assert_eq!((finger.id, finger.x, finger.y), (5, 0x234, 0x401));
+        assert!(parsed.touch_points[1].is_none());
+    }
+
+    #[test]
+    fn ds4_battery_uses_the_ds4_status_layout() {
+        let state = |status| {
+            let battery = ds4_battery(status);
+            (battery & 0x0F, battery >> 4)
+        };
+        assert_eq!(state(0x07), (7, PowerState::Discharging.bits()));
+        assert_eq!(state(0x0B), (10, PowerState::Discharging.bits()));
+        assert_eq!(state(0x14), (4, PowerState::Charging.bits()));
+        assert_eq!(state(0x1B), (10, PowerState::Complete.bits()));
+        assert_eq!(state(0x1E), (0, PowerState::ChargingError.bits()));
+    }
+
+    #[test]
+    fn ps4_bt_corrupted_crc_is_rejected() {
+        let mut buf = ps4_bt_report();
+        buf[74] ^= 0xFF;
+        assert!(matches!(
+            InputDevice::DualShock4.parse(&buf),
+            Err(ParseError::Crc { .. })
+        ));
+    }
+
+    #[test]
+    fn short_report_is_dispatched_by_length() {
+        for device in [InputDevice::DualShock4, InputDevice::DualSense] {
+            let parsed = device.parse(&SHORT_REPORT).unwrap();
+            assert_eq!(parsed.report_id, DS4_BT_SHORT_REPORT_ID);
+            assert_eq!(
+                (parsed.lx, parsed.ly, parsed.rx, parsed.ry),
+                (0x12, 0x34, 0x56, 0x78)
+            );
+            assert_eq!((parsed.l2_axis, parsed.r2_axis), (0x9A, 0xBC));
+            assert!(matches!(parsed.hat, HatDirection::South));
+            assert_eq!(parsed.face_buttons.bits(), FaceButtons::CROSS.bits());
+            assert_eq!(parsed.system_buttons.bits(), SystemButtons::PS.bits());
+            assert!(parsed.touch_points.iter().all(Option::is_none));
+        }
+    }
+
+    #[test]
+    fn unknown_lengths_are_rejected() {
+        assert!(matches!(
+            InputDevice::DualShock4.parse(&SHORT_REPORT[..9]),
+            Err(ParseError::Length {
+                expected: DS4_BT_SHORT_REPORT_LEN,
+                found: 9
+            })
+        ));
+        assert!(matches!(
+            InputDevice::DualShock4.parse(&ps4_bt_report()[..70]),
+            Err(ParseError::Length {
+                expected: DS4_BT_REPORT_LEN,
+                found: 70
+            })
+        ));
+    }
+
+    // Calibration in the DualSense layout, plus and minus ranges alternating: the gyro spans
+    // 2000, 4000 and 1000 steps for 540 deg/s with a pitch bias of 10, the accelerometer is
+    // centered on x, biased by 100 on y and missing on z.
+    const CALIBRATION: [i16; 17] = [
+        10, 0, 0, 1010, -990, 2000, -2000, 500, -500, 540, 540, 8192, -8192, 8292, -8092, 0, 0,
+    ];
+
+    // CRC32 of 0xA3 followed by the first 37 bytes of the DualSense `CALIBRATION` report.
+    const CALIBRATION_CRC: u32 = 0xA050_01D1;
+
+    fn calibration_report<const N: usize>(report_id: u8, values: [i16; 17]) -> [u8; N] {
+        let mut buf = [0u8; N];
+        buf[0] = report_id;
+        for (i, v) in values.iter().enumerate() {
+            buf[1 + i * 2..3 + i * 2].copy_from_slice(&v.to_le_bytes());
+        }
+        buf
+    }
+
+    fn with_crc(mut buf: [u8; 41]) -> [u8; 41] {
+        let crc = crc32(BT_FEATURE_CRC_SEED, &buf[..37]);
+        buf[37..].copy_from_slice(&crc.to_le_bytes());
+        buf
+    }
+
+    // The DualShock 4 Bluetooth layout lists all plus ranges before the minus ones.
+    fn plus_first(v: [i16; 17]) -> [i16; 17] {
+        let mut out = v;
+        out[3..9].copy_from_slice(&[v[3], v[5], v[7], v[4], v[6], v[8]]);
+        out
+    }
+
+    // Gyro and accelerometer output for raw readings of 1000 on every gyro axis and
+    // (4096, 8292, 16384) on the accelerometer.
+    fn motion(calibration: ImuCalibration) -> Motion {
+        let mut state =
+            ControllerStateInternal::from(ParsedInput::from_ps5_bt_buf(&ps5_bt_report()).unwrap());
+        state.gyro = Axis3D {
+            x: 1000,
+            y: 1000,
+            z: 1000,
+        };
+        state.accel = Axis3D {
+            x: 4096,
+            y: 8292,
+            z: 16384,
+        };
+        calibration.apply(&mut state);
+        state.motion
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-3, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    // What `CALIBRATION` decodes to; z falls back to the nominal range.
+    fn assert_calibrated(motion: Motion) {
+        assert_close(motion.gyro, [540.0, 270.0, 1080.0]);
+        assert_close(motion.accel, [0.5, 1.0, 16384.0 * 4.0 / 32767.0]);
+    }
+
+    #[test]
+    fn calibration_crc_uses_the_feature_seed() {
+        let buf = with_crc(calibration_report(DS_CALIBRATION_REPORT_ID, CALIBRATION));
+        assert_eq!(&buf[37..], &CALIBRATION_CRC.to_le_bytes());
+    }
+
+    #[test]
+    fn ds_calibration_alternates_plus_and_minus() {
+        let usb = calibration_report(DS_CALIBRATION_REPORT_ID, CALIBRATION);
+        assert_calibrated(motion(ImuCalibration::from_ds_report(&usb, false).unwrap()));
+        let bt = with_crc(usb);
+        assert_calibrated(motion(ImuCalibration::from_ds_report(&bt, true).unwrap()));
+    }
+
+    #[test]
+    fn ds4_usb_calibration_alternates_plus_and_minus() {
+        let buf: [u8; 37] = calibration_report(DS4_USB_CALIBRATION_REPORT_ID, CALIBRATION);
+        assert_calibrated(motion(ImuCalibration::from_ds4_usb_report(&buf).unwrap()));
+    }
+
+    #[test]
+    fn ds4_bt_calibration_lists_plus_first() {
+        let buf = with_crc(calibration_report(
+            DS4_BT_CALIBRATION_REPORT_ID,
+            plus_first(CALIBRATION),
+        ));
+        assert_calibrated(motion(ImuCalibration::from_ds4_bt_report(&buf).unwrap()));
+
+        // The alternating layout read as plus-first gives different ranges.
+        let buf = with_crc(calibration_report(
+            DS4_BT_CALIBRATION_REPORT_ID,
+            CALIBRATION,
+        ));
+        let gyro = motion(ImuCalibration::from_ds4_bt_report(&buf).unwrap()).gyro;
+        assert!((gyro[1] - 270.0).abs() > 1.0, "{:?}", gyro);
+    }
+
+    #[test]
+    fn bluetooth_calibration_with_a_bad_crc_is_rejected() {
+        let ds: [u8; 41] = calibration_report(DS_CALIBRATION_REPORT_ID, CALIBRATION);
+        assert!(matches!(
+            ImuCalibration::from_ds_report(&ds, true),
+            Err(ParseError::Crc {
+                expected: CALIBRATION_CRC,
+                found: 0
+            })
+        ));
+        let mut ds4 = with_crc(calibration_report(
+            DS4_BT_CALIBRATION_REPORT_ID,
+            plus_first(CALIBRATION),
+        ));
+        ds4[5] ^= 0x01;
+        assert!(matches!(
+            ImuCalibration::from_ds4_bt_report(&ds4),
+            Err(ParseError::Crc { .. })
+        ));
+    }
+
+    #[test]
+    fn empty_ranges_fall_back_to_nominal_scale() {
+        let mut values = [0; 17];
+        values[9..11].copy_from_slice(&[540, 540]);
+        let buf: [u8; 41] = calibration_report(DS_CALIBRATION_REPORT_ID, values);
+        let nominal = motion(ImuCalibration::default());
+        let parsed = motion(ImuCalibration::from_ds_report(&buf, false).unwrap());
+        assert_close(parsed.gyro, nominal.gyro);
+        assert_close(parsed.accel, nominal.accel);
+        assert_close(parsed.gyro, [1000.0 * 2048.0 / 32767.0; 3]);
+    }
+}
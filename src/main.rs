@@ -1,17 +1,25 @@
-use std::{env, sync::Arc};
+use std::{env, path::PathBuf, sync::Arc};
 
 #[cfg(target_os = "linux")]
 use bluetooth::DualSenseController;
 #[cfg(not(target_os = "linux"))]
 use bluetooth_faker::DualSenseController;
+use config::Config;
 use hidapi::HidApi;
-use interfaces::{bluetooth::ControllerState, internal::ControllerStateInternal, usb::ParsedInput};
+use interfaces::{
+    bluetooth::ControllerState,
+    internal::ControllerStateInternal,
+    usb::{ImuCalibration, InputDevice, SONY_VENDOR_ID},
+};
+use profile::ProfileEngine;
 
 #[cfg(target_os = "linux")]
 mod bluetooth;
 #[cfg(not(target_os = "linux"))]
 mod bluetooth_faker;
+mod config;
 pub mod interfaces;
+mod profile;
 
 async fn init_bluetooth() -> Arc<DualSenseController> {
     let controller = Arc::new(DualSenseController::new());
@@ -41,6 +49,23 @@ fn parse_vid_pid(args: &[String], default_vendor_id: u16, default_product_id: u1
         .unwrap_or((default_vendor_id, default_product_id))
 }
 
+fn parse_config_path(args: &[String]) -> Option<PathBuf> {
+    args.iter()
+        .position(|v| v.as_str() == "--config")
+        .and_then(|pos| args.get(pos + 1))
+        .map(PathBuf::from)
+}
+
+fn default_product_id(args: &[String]) -> Option<u16> {
+    if args.iter().any(|v| v.as_str() == "ps5") {
+        Some(0x0CE6)
+    } else if args.iter().any(|v| v.as_str() == "ps4") {
+        Some(0x09CC)
+    } else {
+        None
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = env::args().collect::<Vec<String>>();
@@ -54,51 +79,75 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 device.product_string().unwrap_or("Unknown")
             );
         }
-    } else if args.iter().find(|v| v.as_str() == "ps5").is_some() {
-        let controller = init_bluetooth().await;
-        let (vendor_id, product_id) = parse_vid_pid(&args, 0x054C, 0x0CE6);
+    } else if let Some(default_product_id) = default_product_id(&args) {
+        let (vendor_id, product_id) = parse_vid_pid(&args, SONY_VENDOR_ID, default_product_id);
+        let input = InputDevice::from_vid_pid(vendor_id, product_id).ok_or_else(|| {
+            format!(
+                "unsupported controller {:04x}:{:04x}",
+                vendor_id, product_id
+            )
+        })?;
 
-        let api = HidApi::new()?;
-        let device = api.open(vendor_id, product_id)?;
-        let mut buf = [0u8; 64];
-        println!(
-            "Reading from USB device {:04x}:{:04x}...",
-            vendor_id, product_id
-        );
-        loop {
-            match device.read(&mut buf) {
-                Ok(_) => {
-                    let parsed = ControllerStateInternal::from(ParsedInput::from_ps5_buf(&buf));
-                    // dbg!(&parsed);
-                    controller.update_state(move |state| {
-                        *state = ControllerState::from(parsed);
-                    });
-                }
-                Err(e) => {
-                    eprintln!("Read error: {:?}", e);
-                    break;
-                }
+        let config_path = parse_config_path(&args);
+        let config = match &config_path {
+            Some(path) => {
+                let config = Config::load(path)?;
+                println!(
+                    "Loaded {} profile(s) from {}",
+                    config.profiles.len(),
+                    path.display()
+                );
+                config
             }
+            None => Config::default(),
+        };
+        let mut engine = ProfileEngine::new(config, input);
+        #[cfg(target_os = "linux")]
+        if let Some(path) = config_path {
+            let pending = engine.pending_config();
+            tokio::spawn(async move {
+                if let Err(e) = config::watch(path, pending).await {
+                    eprintln!("Config watcher stopped: {}", e);
+                }
+            });
         }
-    } else if args.iter().find(|v| v.as_str() == "ps4").is_some() {
         let controller = init_bluetooth().await;
-        let (vendor_id, product_id) = parse_vid_pid(&args, 0x054C, 0x09CC);
-
         let api = HidApi::new()?;
         let device = api.open(vendor_id, product_id)?;
-        let mut buf = [0u8; 64];
+        let calibration = input.read_calibration(&device).unwrap_or_else(|e| {
+            eprintln!("Using nominal IMU scale: {}", e);
+            ImuCalibration::default()
+        });
+        // Large enough for the 78 byte Bluetooth reports as well as the 64 byte USB ones.
+        let mut buf = [0u8; 128];
+        let mut status = engine.status();
         println!(
-            "Reading from USB device {:04x}:{:04x}...",
-            vendor_id, product_id
+            "Reading from device {:04x}:{:04x} ({:?})...",
+            vendor_id, product_id, input
         );
         loop {
             match device.read(&mut buf) {
-                Ok(_) => {
-                    let parsed = ControllerStateInternal::from(ParsedInput::from_ps5_buf(&buf));
-                    controller.update_state(move |state| {
-                        *state = ControllerState::from(parsed);
-                    });
-                }
+                Ok(len) => match input.parse(&buf[..len]) {
+                    Ok(parsed) => {
+                        let mut parsed = ControllerStateInternal::from(parsed);
+                        calibration.apply(&mut parsed);
+                        let parsed = engine.process(parsed);
+                        let current = engine.status();
+                        if current.changed_since(&status) {
+                            println!("{}", current);
+                            status = current;
+                        }
+                        // dbg!(&parsed);
+                        controller.update_state(move |state| {
+                            // Movement the report loop has not sent yet adds up.
+                            let pending = state.mouse;
+                            *state = ControllerState::from(parsed);
+                            state.mouse.dx += pending.dx;
+                            state.mouse.dy += pending.dy;
+                        });
+                    }
+                    Err(e) => eprintln!("Dropped report: {}", e),
+                },
                 Err(e) => {
                     eprintln!("Read error: {:?}", e);
                     break;
@@ -0,0 +1,357 @@
+use serde::Deserialize;
+
+use crate::interfaces::internal::{Axis2D, Buttons};
+
+use super::stick::{from_unit, to_unit};
+
+/// All four D-pad directions.
+pub const HAT: Buttons = Buttons::HAT_UP
+    .union(Buttons::HAT_DOWN)
+    .union(Buttons::HAT_LEFT)
+    .union(Buttons::HAT_RIGHT);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+/// Reads a stick as an 8-way hat.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StickToDpad {
+    pub stick: Stick,
+    /// Deflection (0.0 - 1.0) needed before a direction is pressed.
+    pub threshold: f32,
+    /// Width in degrees (0 - 90) of each diagonal sector, the cardinal sectors get the rest.
+    pub diagonal: f32,
+    /// Keeps sending the stick as well; by default it is reported centered.
+    pub keep_stick: bool,
+}
+
+impl Default for StickToDpad {
+    fn default() -> Self {
+        Self {
+            stick: Stick::Left,
+            threshold: 0.5,
+            diagonal: 45.0,
+            keep_stick: false,
+        }
+    }
+}
+
+impl StickToDpad {
+    pub fn directions(&self, stick: Axis2D) -> Buttons {
+        let (x, y) = to_unit(stick);
+        if x.hypot(y) < self.threshold {
+            return Buttons::empty();
+        }
+
+        // Counter-clockwise from the right, stick y grows downwards.
+        let angle = (-y).atan2(x).to_degrees().rem_euclid(360.0);
+        let from_diagonal = (angle.rem_euclid(90.0) - 45.0).abs();
+        let (horizontal, vertical) = if from_diagonal < self.diagonal / 2.0 {
+            (true, true)
+        } else {
+            let cardinal = (angle / 90.0).round() as u32 % 2;
+            (cardinal == 0, cardinal == 1)
+        };
+
+        let mut buttons = Buttons::empty();
+        if horizontal {
+            buttons |= if x > 0.0 {
+                Buttons::HAT_RIGHT
+            } else {
+                Buttons::HAT_LEFT
+            };
+        }
+        if vertical {
+            buttons |= if y < 0.0 {
+                Buttons::HAT_UP
+            } else {
+                Buttons::HAT_DOWN
+            };
+        }
+        buttons
+    }
+}
+
+/// Simultaneous opposite cardinal directions, as fightsticks can send them. Opposites pressed
+/// on the same frame have no order and resolve to neutral under `LastWin` and `FirstWin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Socd {
+    /// The most recently pressed of the two wins.
+    LastWin,
+    /// The one held longer wins, the later press is ignored until the first is released.
+    FirstWin,
+    /// Both cancel out.
+    Neutral,
+    /// Up wins over down, left and right cancel out.
+    UpPriority,
+}
+
+/// D-pad conversions of a profile.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DpadSettings {
+    pub stick_to_dpad: Option<StickToDpad>,
+    /// Sends the D-pad as full deflection of this stick instead of as the hat.
+    pub dpad_to_stick: Option<Stick>,
+    /// Without a policy, any opposite directions report the whole hat as centered.
+    pub socd: Option<Socd>,
+}
+
+// The two directions of each hat axis.
+const AXES: [(Buttons, Buttons); 2] = [
+    (Buttons::HAT_UP, Buttons::HAT_DOWN),
+    (Buttons::HAT_LEFT, Buttons::HAT_RIGHT),
+];
+
+/// Remembers which direction of each axis was pressed last, for the policies that depend on
+/// press order.
+#[derive(Debug)]
+pub struct SocdState {
+    previous: Buttons,
+    last: Buttons,
+}
+
+impl Default for SocdState {
+    fn default() -> Self {
+        Self {
+            previous: Buttons::empty(),
+            last: Buttons::empty(),
+        }
+    }
+}
+
+impl SocdState {
+    /// Resolves opposite directions in `buttons`, the other buttons pass unchanged.
+    pub fn apply(&mut self, policy: Socd, buttons: Buttons) -> Buttons {
+        let hat = buttons & HAT;
+        let pressed = hat - self.previous;
+        self.previous = hat;
+        for (a, b) in AXES {
+            // Both pressed on the same frame leaves the order unknown.
+            if pressed.contains(a | b) {
+                self.last -= a | b;
+            } else if pressed.contains(a) {
+                self.last = (self.last - b) | a;
+            } else if pressed.contains(b) {
+                self.last = (self.last - a) | b;
+            }
+        }
+
+        let mut resolved = hat;
+        for (a, b) in AXES {
+            if !hat.contains(a | b) {
+                continue;
+            }
+            let last = self.last & (a | b);
+            resolved -= a | b;
+            resolved |= match policy {
+                Socd::LastWin => last,
+                Socd::FirstWin if last.is_empty() => Buttons::empty(),
+                Socd::FirstWin => (a | b) - last,
+                Socd::Neutral => Buttons::empty(),
+                Socd::UpPriority if a == Buttons::HAT_UP => Buttons::HAT_UP,
+                Socd::UpPriority => Buttons::empty(),
+            };
+        }
+        (buttons - HAT) | resolved
+    }
+}
+
+/// Full deflection of a stick for the held D-pad directions.
+pub fn dpad_to_stick(buttons: Buttons) -> Axis2D {
+    let axis = |negative, positive| match (buttons.contains(negative), buttons.contains(positive)) {
+        (true, false) => -1.0,
+        (false, true) => 1.0,
+        _ => 0.0,
+    };
+    from_unit(
+        axis(Buttons::HAT_LEFT, Buttons::HAT_RIGHT),
+        axis(Buttons::HAT_UP, Buttons::HAT_DOWN),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UP: Buttons = Buttons::HAT_UP;
+    const DOWN: Buttons = Buttons::HAT_DOWN;
+    const LEFT: Buttons = Buttons::HAT_LEFT;
+    const RIGHT: Buttons = Buttons::HAT_RIGHT;
+
+    // Feeds the frames in order and returns what each of them resolved to.
+    fn run(policy: Socd, frames: &[Buttons]) -> Vec<Buttons> {
+        let mut state = SocdState::default();
+        frames.iter().map(|b| state.apply(policy, *b)).collect()
+    }
+
+    #[test]
+    fn single_directions_pass_through() {
+        for policy in [
+            Socd::LastWin,
+            Socd::FirstWin,
+            Socd::Neutral,
+            Socd::UpPriority,
+        ] {
+            let frames = [UP, UP | LEFT, DOWN | RIGHT, Buttons::empty()];
+            assert_eq!(run(policy, &frames), frames, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn other_buttons_are_untouched() {
+        let out = run(
+            Socd::Neutral,
+            &[LEFT | RIGHT | Buttons::CROSS | Buttons::L1],
+        );
+        assert_eq!(out, [Buttons::CROSS | Buttons::L1]);
+    }
+
+    #[test]
+    fn neutral_cancels_each_axis_on_its_own() {
+        let out = run(
+            Socd::Neutral,
+            &[LEFT, LEFT | RIGHT, LEFT | RIGHT | UP, UP | DOWN | RIGHT],
+        );
+        assert_eq!(out, [LEFT, Buttons::empty(), UP, RIGHT]);
+    }
+
+    #[test]
+    fn last_win_follows_the_latest_press() {
+        let frames = [LEFT, LEFT | RIGHT, RIGHT, LEFT | RIGHT, LEFT];
+        let out = run(Socd::LastWin, &frames);
+        assert_eq!(out, [LEFT, RIGHT, RIGHT, LEFT, LEFT]);
+    }
+
+    #[test]
+    fn first_win_keeps_the_held_direction() {
+        let frames = [LEFT, LEFT | RIGHT, RIGHT, LEFT | RIGHT, LEFT];
+        let out = run(Socd::FirstWin, &frames);
+        assert_eq!(out, [LEFT, LEFT, RIGHT, RIGHT, LEFT]);
+    }
+
+    #[test]
+    fn same_frame_opposites_are_neutral_until_one_is_released() {
+        for policy in [Socd::LastWin, Socd::FirstWin] {
+            let out = run(policy, &[UP | DOWN, UP | DOWN, DOWN]);
+            assert_eq!(
+                out,
+                [Buttons::empty(), Buttons::empty(), DOWN],
+                "{:?}",
+                policy
+            );
+        }
+    }
+
+    #[test]
+    fn up_priority_only_favours_up() {
+        let frames = [DOWN, UP | DOWN, UP | DOWN | LEFT | RIGHT, LEFT | RIGHT];
+        let out = run(Socd::UpPriority, &frames);
+        assert_eq!(out, [DOWN, UP, UP, Buttons::empty()]);
+    }
+
+    #[test]
+    fn axes_are_tracked_independently() {
+        let frames = [UP, UP | LEFT, UP | LEFT | DOWN, UP | LEFT | DOWN | RIGHT];
+        let out = run(Socd::LastWin, &frames);
+        assert_eq!(out, [UP, UP | LEFT, DOWN | LEFT, DOWN | RIGHT]);
+    }
+
+    // Full deflection at `degrees` counter-clockwise from the right.
+    fn at(degrees: f32) -> Axis2D {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        from_unit(cos, -sin)
+    }
+
+    fn to_dpad(diagonal: f32) -> StickToDpad {
+        StickToDpad {
+            diagonal,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn threshold_is_inclusive() {
+        let settings = StickToDpad::default();
+        let stick = |x| settings.directions(Axis2D { x, y: 128 });
+        // 64 / 127 and 63 / 127 on the positive side, -64 / 128 on the negative one.
+        assert_eq!(stick(192), RIGHT);
+        assert_eq!(stick(191), Buttons::empty());
+        assert_eq!(stick(64), LEFT);
+        assert_eq!(stick(65), Buttons::empty());
+        assert_eq!(stick(128), Buttons::empty());
+    }
+
+    #[test]
+    fn eight_directions() {
+        let settings = StickToDpad::default();
+        let expected = [
+            RIGHT,
+            UP | RIGHT,
+            UP,
+            UP | LEFT,
+            LEFT,
+            DOWN | LEFT,
+            DOWN,
+            DOWN | RIGHT,
+        ];
+        for (i, buttons) in expected.into_iter().enumerate() {
+            let degrees = i as f32 * 45.0;
+            assert_eq!(settings.directions(at(degrees)), buttons, "{}", degrees);
+            // Each sector spans 22.5 degrees to either side.
+            assert_eq!(
+                settings.directions(at(degrees - 20.0)),
+                buttons,
+                "{}",
+                degrees
+            );
+            assert_eq!(
+                settings.directions(at(degrees + 20.0)),
+                buttons,
+                "{}",
+                degrees
+            );
+        }
+        assert_eq!(settings.directions(at(24.0)), UP | RIGHT);
+        assert_eq!(settings.directions(at(21.0)), RIGHT);
+    }
+
+    #[test]
+    fn zero_diagonal_only_gives_cardinals() {
+        let settings = to_dpad(0.0);
+        assert_eq!(settings.directions(at(44.0)), RIGHT);
+        assert_eq!(settings.directions(at(46.0)), UP);
+        assert_eq!(settings.directions(at(224.0)), LEFT);
+        assert_eq!(settings.directions(at(226.0)), DOWN);
+    }
+
+    #[test]
+    fn full_diagonal_leaves_cardinals_only_on_the_axes() {
+        let settings = to_dpad(90.0);
+        assert_eq!(settings.directions(at(0.0)), RIGHT);
+        assert_eq!(settings.directions(at(3.0)), UP | RIGHT);
+        assert_eq!(settings.directions(at(87.0)), UP | RIGHT);
+        assert_eq!(settings.directions(at(90.0)), UP);
+        assert_eq!(settings.directions(at(183.0)), DOWN | LEFT);
+    }
+
+    #[test]
+    fn dpad_deflects_the_stick_fully() {
+        let stick = |buttons| {
+            let stick = dpad_to_stick(buttons);
+            (stick.x, stick.y)
+        };
+        assert_eq!(stick(Buttons::empty()), (128, 128));
+        assert_eq!(stick(UP), (128, 0));
+        assert_eq!(stick(DOWN | RIGHT), (255, 255));
+        assert_eq!(stick(UP | LEFT | Buttons::CROSS), (0, 0));
+        // Opposites cancel out on their axis only.
+        assert_eq!(stick(LEFT | RIGHT | DOWN), (128, 255));
+    }
+}
@@ -0,0 +1,193 @@
+use std::{f32::consts::PI, time::Instant};
+
+use serde::Deserialize;
+
+use crate::interfaces::internal::Axis2D;
+
+use super::stick::{from_unit, to_unit};
+
+/// Cutoff in Hz of the one-euro filter's speed estimate.
+const SPEED_CUTOFF: f32 = 1.0;
+
+/// Hides the overshoot past center when a stick springs back after being let go.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapbackSettings {
+    /// Speed in full deflections per second above which a crossing of the center is a snapback.
+    pub speed: f32,
+    /// Frames the stick is reported centered after a snapback.
+    pub frames: u32,
+}
+
+impl Default for SnapbackSettings {
+    fn default() -> Self {
+        Self {
+            speed: 40.0,
+            frames: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum JitterFilter {
+    /// Exponential moving average, `alpha` (0.0 - 1.0) is the weight of each new sample.
+    Ema { alpha: f32 },
+    /// Smooths with `min_cutoff` Hz while the stick rests, the cutoff grows by `beta` Hz per
+    /// full deflection per second of movement so fast motion does not lag.
+    OneEuro {
+        #[serde(default = "default_min_cutoff")]
+        min_cutoff: f32,
+        #[serde(default = "default_beta")]
+        beta: f32,
+    },
+}
+
+fn default_min_cutoff() -> f32 {
+    1.0
+}
+
+fn default_beta() -> f32 {
+    0.5
+}
+
+/// Stick filtering of a profile, applied to both sticks before any other processing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterSettings {
+    pub snapback: Option<SnapbackSettings>,
+    pub jitter: Option<JitterFilter>,
+}
+
+impl FilterSettings {
+    pub fn is_passthrough(&self) -> bool {
+        self.snapback.is_none() && self.jitter.is_none()
+    }
+}
+
+/// Filter state of one stick.
+#[derive(Debug, Default)]
+pub struct StickFilter {
+    // Raw position of the previous frame.
+    previous: Option<((f32, f32), Instant)>,
+    // Frames left to hold the stick centered.
+    suppress: u32,
+    filtered: Option<(f32, f32)>,
+    // Smoothed speed for the one-euro cutoff.
+    speed: f32,
+}
+
+impl StickFilter {
+    pub fn apply(&mut self, settings: &FilterSettings, stick: Axis2D, now: Instant) -> Axis2D {
+        let (mut x, mut y) = to_unit(stick);
+        let previous = self.previous.replace(((x, y), now));
+        let dt = previous
+            .map(|(_, last)| now.duration_since(last).as_secs_f32())
+            .filter(|dt| *dt > 0.0);
+
+        if let Some(snapback) = &settings.snapback {
+            if let (Some(((px, py), _)), Some(dt)) = (previous, dt) {
+                let crossed = px * x + py * y < 0.0;
+                if crossed && (x - px).hypot(y - py) / dt >= snapback.speed {
+                    self.suppress = snapback.frames;
+                }
+            }
+            if self.suppress > 0 {
+                self.suppress -= 1;
+                (x, y) = (0.0, 0.0);
+            }
+        }
+
+        if let Some(jitter) = settings.jitter {
+            let (fx, fy) = self.filtered.unwrap_or((x, y));
+            let alpha = match (jitter, dt) {
+                (JitterFilter::Ema { alpha }, _) => alpha,
+                (JitterFilter::OneEuro { min_cutoff, beta }, Some(dt)) => {
+                    let speed = (x - fx).hypot(y - fy) / dt;
+                    self.speed += smoothing(SPEED_CUTOFF, dt) * (speed - self.speed);
+                    smoothing(min_cutoff + beta * self.speed, dt)
+                }
+                (JitterFilter::OneEuro { .. }, None) => 1.0,
+            };
+            (x, y) = (fx + alpha * (x - fx), fy + alpha * (y - fy));
+            self.filtered = Some((x, y));
+        }
+
+        from_unit(x, y)
+    }
+}
+
+// Weight of a new sample in a first order low-pass with the given cutoff frequency.
+fn smoothing(cutoff: f32, dt: f32) -> f32 {
+    let tau = 1.0 / (2.0 * PI * cutoff);
+    1.0 / (1.0 + tau / dt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    fn settings(
+        snapback: Option<SnapbackSettings>,
+        jitter: Option<JitterFilter>,
+    ) -> FilterSettings {
+        FilterSettings { snapback, jitter }
+    }
+
+    // Feeds x positions `step_ms` apart with y centered, returns the filtered x of each.
+    fn run(settings: &FilterSettings, step_ms: u64, xs: &[u8]) -> Vec<u8> {
+        let start = Instant::now();
+        let mut filter = StickFilter::default();
+        xs.iter()
+            .enumerate()
+            .map(|(i, &x)| {
+                let now = start + Duration::from_millis(step_ms * i as u64);
+                filter.apply(settings, Axis2D { x, y: 128 }, now).x
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fast_crossing_is_held_centered_for_the_given_frames() {
+        let settings = settings(Some(SnapbackSettings::default()), None);
+        // Full right to full left in 10ms is 200 deflections per second.
+        let out = run(&settings, 10, &[255, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(out, [255, 128, 128, 128, 128, 0, 0]);
+    }
+
+    #[test]
+    fn slow_crossing_passes() {
+        let settings = settings(Some(SnapbackSettings::default()), None);
+        // About half a deflection in 100ms is 5 per second.
+        let out = run(&settings, 100, &[160, 96, 0]);
+        assert_eq!(out, [160, 96, 0]);
+    }
+
+    #[test]
+    fn ema_converges_by_alpha_per_frame() {
+        let settings = settings(None, Some(JitterFilter::Ema { alpha: 0.25 }));
+        let out = run(&settings, 10, &[128, 255, 255, 255, 255]);
+        for (n, x) in out.iter().enumerate() {
+            let expected = from_unit(1.0 - 0.75f32.powi(n as i32), 0.0).x;
+            assert_eq!(*x, expected, "frame {}", n);
+        }
+    }
+
+    #[test]
+    fn one_euro_passes_a_step_without_elapsed_time() {
+        let settings = settings(
+            None,
+            Some(JitterFilter::OneEuro {
+                min_cutoff: 1.0,
+                beta: 0.5,
+            }),
+        );
+        // Both samples share a timestamp, so there is no dt to smooth over.
+        assert_eq!(run(&settings, 0, &[128, 255]), [128, 255]);
+        // With time in between the same step is smoothed.
+        let out = run(&settings, 10, &[128, 255]);
+        assert!(out[1] > 128 && out[1] < 255, "{:?}", out);
+    }
+}
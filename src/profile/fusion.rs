@@ -0,0 +1,237 @@
+use crate::interfaces::internal::SensorClock;
+
+/// Gaps between two samples longer than this are not integrated, e.g. after dropped reports.
+const MAX_GAP: f32 = 0.1;
+
+/// Madgwick filter gain: how strongly the gravity vector pulls the gyro integration back.
+const BETA: f32 = 0.1;
+
+/// Orientation of the controller relative to lying flat, face up, with the far edge pointing
+/// away from the player.
+///
+/// The frame is right-handed with x to the right, y forward (away from the player) and z up,
+/// so the identity quaternion is the controller lying flat on a table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self {
+            w: 1.0,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+}
+
+impl Quaternion {
+    /// Pitch, roll and yaw in degrees.
+    pub fn euler_degrees(&self) -> (f32, f32, f32) {
+        let Quaternion { w, x, y, z } = *self;
+        let roll = (2.0 * (w * y - z * x)).clamp(-1.0, 1.0).asin();
+        let pitch = (2.0 * (w * x + y * z)).atan2(1.0 - 2.0 * (x * x + y * y));
+        let yaw = (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z));
+        (pitch.to_degrees(), roll.to_degrees(), yaw.to_degrees())
+    }
+
+    fn normalized(self) -> Self {
+        let norm = (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
+        if norm == 0.0 {
+            return Self::default();
+        }
+        Self {
+            w: self.w / norm,
+            x: self.x / norm,
+            y: self.y / norm,
+            z: self.z / norm,
+        }
+    }
+
+    // Tilt from the gravity vector alone, yaw is unknown and left at 0.
+    fn from_gravity([ax, ay, az]: [f32; 3]) -> Self {
+        let pitch = ay.atan2(az);
+        let roll = (-ax).atan2(ay.hypot(az));
+        let (sp, cp) = (pitch / 2.0).sin_cos();
+        let (sr, cr) = (roll / 2.0).sin_cos();
+        Self {
+            w: cp * cr,
+            x: sp * cr,
+            y: cp * sr,
+            z: -sp * sr,
+        }
+    }
+}
+
+/// Fuses gyro and accelerometer into an orientation with a Madgwick filter, timed by the
+/// controller's own sensor timestamps.
+#[derive(Debug)]
+pub struct Fusion {
+    clock: SensorClock,
+    orientation: Option<Quaternion>,
+    last_ts: Option<u32>,
+}
+
+impl Fusion {
+    pub fn new(clock: SensorClock) -> Self {
+        Self {
+            clock,
+            orientation: None,
+            last_ts: None,
+        }
+    }
+
+    pub fn orientation(&self) -> Quaternion {
+        self.orientation.unwrap_or_default()
+    }
+
+    /// Feeds one sample: `gyro` in deg/s, `accel` in g, both in the controller's axes.
+    pub fn update(&mut self, ts: u32, gyro: [f32; 3], accel: [f32; 3]) {
+        // Controller axes are x right, y out of the face, z towards the player.
+        let [gx, gy, gz] = gyro.map(f32::to_radians);
+        let gyro = [gx, -gz, gy];
+        let accel = [accel[0], -accel[2], accel[1]];
+
+        let dt = self
+            .last_ts
+            .map(|last| self.clock.seconds_between(last, ts));
+        self.last_ts = Some(ts);
+
+        let Some(q) = self.orientation else {
+            if accel != [0.0; 3] {
+                self.orientation = Some(Quaternion::from_gravity(accel));
+            }
+            return;
+        };
+        // Reports without sensor data repeat the timestamp.
+        let Some(dt) = dt.filter(|dt| *dt > 0.0 && *dt <= MAX_GAP) else {
+            return;
+        };
+        self.orientation = Some(madgwick(q, gyro, accel, dt));
+    }
+}
+
+// One step of Madgwick's IMU filter: integrates the gyro rate and applies a gradient descent
+// step that turns the estimated gravity direction towards the measured one.
+fn madgwick(q: Quaternion, [gx, gy, gz]: [f32; 3], accel: [f32; 3], dt: f32) -> Quaternion {
+    let Quaternion {
+        w: q0,
+        x: q1,
+        y: q2,
+        z: q3,
+    } = q;
+
+    let mut dq = [
+        0.5 * (-q1 * gx - q2 * gy - q3 * gz),
+        0.5 * (q0 * gx + q2 * gz - q3 * gy),
+        0.5 * (q0 * gy - q1 * gz + q3 * gx),
+        0.5 * (q0 * gz + q1 * gy - q2 * gx),
+    ];
+
+    let norm = accel.iter().map(|a| a * a).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        let [ax, ay, az] = accel.map(|a| a / norm);
+        let s = [
+            4.0 * q0 * q2 * q2 + 2.0 * q2 * ax + 4.0 * q0 * q1 * q1 - 2.0 * q1 * ay,
+            4.0 * q1 * q3 * q3 - 2.0 * q3 * ax + 4.0 * q0 * q0 * q1 - 2.0 * q0 * ay - 4.0 * q1
+                + 8.0 * q1 * q1 * q1
+                + 8.0 * q1 * q2 * q2
+                + 4.0 * q1 * az,
+            4.0 * q0 * q0 * q2 + 2.0 * q0 * ax + 4.0 * q2 * q3 * q3 - 2.0 * q3 * ay - 4.0 * q2
+                + 8.0 * q2 * q1 * q1
+                + 8.0 * q2 * q2 * q2
+                + 4.0 * q2 * az,
+            4.0 * q1 * q1 * q3 - 2.0 * q1 * ax + 4.0 * q2 * q2 * q3 - 2.0 * q2 * ay,
+        ];
+        let s_norm = s.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if s_norm > 0.0 {
+            for (d, s) in dq.iter_mut().zip(s) {
+                *d -= BETA * s / s_norm;
+            }
+        }
+    }
+
+    Quaternion {
+        w: q0 + dq[0] * dt,
+        x: q1 + dq[1] * dt,
+        y: q2 + dq[2] * dt,
+        z: q3 + dq[3] * dt,
+    }
+    .normalized()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOCK: SensorClock = SensorClock {
+        ticks_per_second: 3_000_000,
+        bits: 32,
+    };
+    // 10ms between samples.
+    const STEP: u32 = 30_000;
+    // Controller lying flat, face up.
+    const FLAT: [f32; 3] = [0.0, 1.0, 0.0];
+
+    fn flat() -> Fusion {
+        let mut fusion = Fusion::new(CLOCK);
+        fusion.update(0, [0.0; 3], FLAT);
+        assert_eq!(fusion.orientation(), Quaternion::default());
+        fusion
+    }
+
+    #[test]
+    fn repeated_timestamp_is_skipped() {
+        let mut fusion = flat();
+        fusion.update(0, [90.0, 0.0, 0.0], FLAT);
+        assert_eq!(fusion.orientation(), Quaternion::default());
+
+        fusion.update(STEP, [90.0, 0.0, 0.0], FLAT);
+        assert_ne!(fusion.orientation(), Quaternion::default());
+    }
+
+    #[test]
+    fn long_gap_is_skipped() {
+        let mut fusion = flat();
+        let gap = (MAX_GAP * CLOCK.ticks_per_second as f32) as u32 + STEP;
+        fusion.update(gap, [90.0, 0.0, 0.0], FLAT);
+        assert_eq!(fusion.orientation(), Quaternion::default());
+
+        // The next sample is timed from the skipped one.
+        fusion.update(gap + STEP, [90.0, 0.0, 0.0], FLAT);
+        assert_ne!(fusion.orientation(), Quaternion::default());
+    }
+
+    // Feeds `accel` with the controller held still until the estimate has settled.
+    fn settle(accel: [f32; 3]) -> (f32, f32, f32) {
+        let mut fusion = flat();
+        for i in 1..=2000 {
+            fusion.update(i * STEP, [0.0; 3], accel);
+        }
+        fusion.orientation().euler_degrees()
+    }
+
+    fn assert_close(actual: (f32, f32, f32), expected: (f32, f32, f32)) {
+        let (a, e) = (
+            [actual.0, actual.1, actual.2],
+            [expected.0, expected.1, expected.2],
+        );
+        for (a, e) in a.iter().zip(e) {
+            assert!((a - e).abs() < 0.5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn settles_onto_gravity() {
+        let (sin, cos) = 30f32.to_radians().sin_cos();
+        // Far edge tipped up 30 degrees.
+        assert_close(settle([0.0, cos, -sin]), (30.0, 0.0, 0.0));
+        // Rolled 30 degrees to the right.
+        assert_close(settle([-sin, cos, 0.0]), (0.0, 30.0, 0.0));
+    }
+}
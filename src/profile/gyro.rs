@@ -0,0 +1,236 @@
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::interfaces::internal::{Axis2D, Buttons};
+
+use super::stick::{from_unit, to_unit};
+
+/// Sensor noise below this is still treated as the controller lying still, in deg/s.
+const STILL_THRESHOLD: f32 = 1.5;
+/// How long the controller has to lie still before the offset is adjusted.
+const STILL_TIME: Duration = Duration::from_millis(1000);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GyroOutput {
+    /// Gyro deflection is added on top of the physical right stick.
+    #[default]
+    Add,
+    /// The physical right stick is ignored.
+    Replace,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClutchMode {
+    /// Aiming is paused while the clutch is held, to re-center the controller like lifting a mouse.
+    #[default]
+    Ratchet,
+    /// Aiming only happens while the clutch is held.
+    Enable,
+}
+
+/// Turns gyro angular velocity into right stick deflection.
+#[derive(Debug, Clone)]
+pub struct GyroSettings {
+    /// Stick deflection (fraction of full) per degree per second.
+    pub sensitivity: f32,
+    /// Time constant of the smoothing applied to the gyro, 0 disables it.
+    pub smoothing: Duration,
+    /// Angular velocity below this is ignored, in deg/s.
+    pub deadband: f32,
+    pub clutch: Buttons,
+    pub clutch_mode: ClutchMode,
+    pub output: GyroOutput,
+    pub invert_x: bool,
+    pub invert_y: bool,
+}
+
+/// Calibration offset and smoothing state that carries over between frames.
+#[derive(Debug, Default)]
+pub struct GyroAim {
+    offset: [f32; 3],
+    average: [f32; 3],
+    still_since: Option<Instant>,
+    smoothed: (f32, f32),
+    last: Option<Instant>,
+}
+
+impl GyroAim {
+    /// Feeds a gyro sample in deg/s into the calibration, returns it with the offset removed.
+    ///
+    /// While every axis stays close to its running average the controller is assumed to be
+    /// lying still, and after `STILL_TIME` of that the offset drifts towards the average. This
+    /// catches the drift that the factory calibration leaves behind.
+    pub fn calibrate(&mut self, raw: [f32; 3], now: Instant) -> [f32; 3] {
+        let still = raw
+            .iter()
+            .zip(&self.average)
+            .all(|(v, avg)| (v - avg).abs() < STILL_THRESHOLD);
+        for (avg, v) in self.average.iter_mut().zip(raw) {
+            *avg += (v - *avg) * 0.05;
+        }
+        if !still {
+            self.still_since = None;
+        } else if let Some(since) = self.still_since {
+            if now.duration_since(since) >= STILL_TIME {
+                for (offset, avg) in self.offset.iter_mut().zip(self.average) {
+                    *offset += (avg - *offset) * 0.02;
+                }
+            }
+        } else {
+            self.still_since = Some(now);
+        }
+
+        [0, 1, 2].map(|i| raw[i] - self.offset[i])
+    }
+
+    /// Deflects `stick` by the calibrated angular velocity `gyro` (pitch, yaw, roll in deg/s).
+    pub fn apply(
+        &mut self,
+        settings: &GyroSettings,
+        gyro: [f32; 3],
+        buttons: Buttons,
+        stick: Axis2D,
+        now: Instant,
+    ) -> Axis2D {
+        let dt = self
+            .last
+            .map_or(Duration::ZERO, |last| now.duration_since(last));
+        self.last = Some(now);
+
+        let clutched = buttons.intersects(settings.clutch);
+        let enabled = settings.clutch.is_empty()
+            || match settings.clutch_mode {
+                ClutchMode::Ratchet => !clutched,
+                ClutchMode::Enable => clutched,
+            };
+
+        let deadband = |v: f32| (v.abs() - settings.deadband).max(0.0).copysign(v);
+        let (mut x, mut y) = if enabled {
+            (-deadband(gyro[1]), -deadband(gyro[0]))
+        } else {
+            (0.0, 0.0)
+        };
+        if settings.invert_x {
+            x = -x;
+        }
+        if settings.invert_y {
+            y = -y;
+        }
+
+        if settings.smoothing.is_zero() {
+            self.smoothed = (x, y);
+        } else {
+            let alpha = 1.0 - (-dt.as_secs_f32() / settings.smoothing.as_secs_f32()).exp();
+            self.smoothed.0 += (x - self.smoothed.0) * alpha;
+            self.smoothed.1 += (y - self.smoothed.1) * alpha;
+        }
+
+        let (gx, gy) = (
+            self.smoothed.0 * settings.sensitivity,
+            self.smoothed.1 * settings.sensitivity,
+        );
+        match settings.output {
+            GyroOutput::Add => {
+                let (sx, sy) = to_unit(stick);
+                from_unit(sx + gx, sy + gy)
+            }
+            GyroOutput::Replace => from_unit(gx, gy),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CENTER: Axis2D = Axis2D { x: 128, y: 128 };
+
+    // Full right deflection from 50 deg/s of yaw, with nothing else in the way.
+    fn settings() -> GyroSettings {
+        GyroSettings {
+            sensitivity: 0.02,
+            smoothing: Duration::ZERO,
+            deadband: 0.0,
+            clutch: Buttons::L1,
+            clutch_mode: ClutchMode::Ratchet,
+            output: GyroOutput::Replace,
+            invert_x: false,
+            invert_y: false,
+        }
+    }
+
+    fn aim(settings: &GyroSettings, gyro: [f32; 3], buttons: Buttons, stick: Axis2D) -> (u8, u8) {
+        let stick = GyroAim::default().apply(settings, gyro, buttons, stick, Instant::now());
+        (stick.x, stick.y)
+    }
+
+    #[test]
+    fn constant_bias_is_removed_after_lying_still() {
+        let bias = [3.0, -2.0, 0.5];
+        let start = Instant::now();
+        let mut aim = GyroAim::default();
+        let mut frames = (0..).map(|i| start + Duration::from_millis(10 * i));
+
+        // Not before the controller has been still for STILL_TIME.
+        for now in frames.by_ref().take(100) {
+            assert_eq!(aim.calibrate(bias, now), bias);
+        }
+        let mut out = bias;
+        for now in frames.take(500) {
+            out = aim.calibrate(bias, now);
+        }
+        for v in out {
+            assert!(v.abs() < 0.01, "{:?}", out);
+        }
+
+        // Real motion still comes through on top of the learned offset.
+        let moved = aim.calibrate([33.0, -2.0, 0.5], start + Duration::from_secs(10));
+        assert!((moved[0] - 30.0).abs() < 0.01, "{:?}", moved);
+    }
+
+    #[test]
+    fn ratchet_pauses_while_the_clutch_is_held() {
+        let settings = settings();
+        let gyro = [0.0, -50.0, 0.0];
+        assert_eq!(aim(&settings, gyro, Buttons::empty(), CENTER), (255, 128));
+        assert_eq!(aim(&settings, gyro, Buttons::L1, CENTER), (128, 128));
+    }
+
+    #[test]
+    fn enable_only_aims_while_the_clutch_is_held() {
+        let settings = GyroSettings {
+            clutch_mode: ClutchMode::Enable,
+            ..settings()
+        };
+        let gyro = [0.0, -50.0, 0.0];
+        assert_eq!(aim(&settings, gyro, Buttons::empty(), CENTER), (128, 128));
+        assert_eq!(aim(&settings, gyro, Buttons::L1, CENTER), (255, 128));
+    }
+
+    #[test]
+    fn deadband_is_subtracted_from_the_speed() {
+        let settings = GyroSettings {
+            deadband: 10.0,
+            ..settings()
+        };
+        let none = Buttons::empty();
+        assert_eq!(aim(&settings, [0.0, -8.0, 0.0], none, CENTER), (128, 128));
+        assert_eq!(aim(&settings, [0.0, -35.0, 0.0], none, CENTER), (192, 128));
+        assert_eq!(aim(&settings, [35.0, 0.0, 0.0], none, CENTER), (128, 64));
+    }
+
+    #[test]
+    fn add_keeps_the_physical_stick_and_replace_drops_it() {
+        let left = Axis2D { x: 0, y: 128 };
+        let gyro = [0.0, -50.0, 0.0];
+        let add = GyroSettings {
+            output: GyroOutput::Add,
+            ..settings()
+        };
+        assert_eq!(aim(&add, gyro, Buttons::empty(), left), (128, 128));
+        assert_eq!(aim(&settings(), gyro, Buttons::empty(), left), (255, 128));
+    }
+}
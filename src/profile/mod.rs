@@ -0,0 +1,896 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::time::sleep;
+
+pub mod dpad;
+pub mod filter;
+pub mod fusion;
+pub mod gyro;
+pub mod mouse;
+pub mod stick;
+pub mod tilt;
+pub mod touch;
+pub mod trigger;
+pub mod turbo;
+
+use dpad::{SocdState, Stick};
+use filter::StickFilter;
+use fusion::{Fusion, Quaternion};
+use gyro::GyroAim;
+use mouse::Trackpad;
+use touch::TouchTracker;
+use turbo::TurboMode;
+
+use crate::{
+    config::{Config, PendingConfig},
+    interfaces::internal::{
+        Axis2D, Buttons, ControllerStateInternal, KeyPress, Keyboard, Layer, Macro, MacroAction,
+        MacroMode, Profile, SensitivityProfile,
+    },
+    interfaces::{bluetooth::REPORT_INTERVAL, usb::InputDevice},
+};
+
+/// Output of the macro sequences currently running on tokio, merged into every frame.
+#[derive(Debug)]
+struct MacroOutput {
+    pressed: Buttons,
+    sticks: Option<(Axis2D, Axis2D)>,
+    keyboard: Keyboard,
+}
+
+/// How long a tapped key is held, and released before the next one; two report ticks make
+/// sure every edge reaches the host.
+const KEY_TAP: Duration = Duration::from_millis(2 * REPORT_INTERVAL.as_millis() as u64);
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MacroState {
+    // The trigger combo was held on the previous frame.
+    held: bool,
+    // When the trigger combo was freshly pressed, for long-press macros.
+    held_since: Option<Instant>,
+    // The macro's masks are applied; stays set between presses for toggle macros.
+    active: bool,
+}
+
+impl MacroState {
+    /// Advances the state by one frame, returns whether the macro just became active.
+    fn update(&mut self, m: &Macro, buttons: Buttons, now: Instant) -> bool {
+        let down = m.is_triggered(buttons);
+        let was_active = self.active;
+        match m.mode {
+            MacroMode::Momentary => self.active = down,
+            MacroMode::Toggle => {
+                if down && !self.held {
+                    self.active = !self.active;
+                }
+            }
+            MacroMode::LongPress(duration) => {
+                if down && !self.held {
+                    self.held_since = Some(now);
+                } else if !down {
+                    self.held_since = None;
+                }
+                self.active = self
+                    .held_since
+                    .is_some_and(|since| now.duration_since(since) >= duration);
+            }
+        }
+        self.held = down;
+        self.active && !was_active
+    }
+}
+
+#[derive(Debug, Default)]
+struct LayerState {
+    active: bool,
+    macros: Vec<MacroState>,
+}
+
+/// Button and sensitivity output that the macro stages of a frame build up in turn.
+struct Frame<'a> {
+    buttons: Buttons,
+    sensitivity: &'a str,
+    switch_to: Option<&'a str>,
+    keyboard: Keyboard,
+}
+
+/// Snapshot of the engine for status output.
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub profile: Option<String>,
+    /// Names of the layers whose shift chord is held.
+    pub layers: Vec<String>,
+    /// Trigger buttons of the toggle macros that are currently latched on.
+    pub latched: Buttons,
+    /// Buttons firing from a toggle turbo.
+    pub turbo: Buttons,
+    pub orientation: Quaternion,
+}
+
+impl Status {
+    /// Whether the mapping state differs; the orientation is left out as it moves every frame.
+    pub fn changed_since(&self, previous: &Status) -> bool {
+        self.profile != previous.profile
+            || self.layers != previous.layers
+            || self.latched != previous.latched
+            || self.turbo != previous.turbo
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (pitch, roll, yaw) = self.orientation.euler_degrees();
+        write!(
+            f,
+            "profile: {}, layers: [{}], latched: {:?}, turbo: {:?}, \
+             pitch/roll/yaw: {:.0}/{:.0}/{:.0}",
+            self.profile.as_deref().unwrap_or("-"),
+            self.layers.join(", "),
+            self.latched,
+            self.turbo,
+            pitch,
+            roll,
+            yaw
+        )
+    }
+}
+
+/// Runs the active `Profile` on every frame between parsing and the Bluetooth report.
+pub struct ProfileEngine {
+    profiles: Vec<Profile>,
+    sensitivities: Vec<SensitivityProfile>,
+    active: usize,
+    macros: Vec<MacroState>,
+    layers: Vec<LayerState>,
+    // Buttons currently latched on by each toggle turbo of the active profile.
+    turbo_latched: Vec<Buttons>,
+    gyro: GyroAim,
+    fusion: Fusion,
+    touch: TouchTracker,
+    trackpad: Trackpad,
+    touchpad_size: (u16, u16),
+    socd: SocdState,
+    filters: [StickFilter; 2],
+    // Physical buttons of the previous frame, for edge detection.
+    previous: Buttons,
+    macro_output: Arc<Mutex<MacroOutput>>,
+    pending: PendingConfig,
+}
+
+impl ProfileEngine {
+    pub fn new(config: Config, input: InputDevice) -> Self {
+        let mut engine = Self {
+            profiles: Vec::new(),
+            sensitivities: Vec::new(),
+            active: 0,
+            macros: Vec::new(),
+            layers: Vec::new(),
+            turbo_latched: Vec::new(),
+            gyro: GyroAim::default(),
+            fusion: Fusion::new(input.sensor_clock()),
+            touch: TouchTracker::default(),
+            trackpad: Trackpad::default(),
+            touchpad_size: input.touchpad_size(),
+            socd: SocdState::default(),
+            filters: Default::default(),
+            previous: Buttons::empty(),
+            macro_output: Arc::new(Mutex::new(MacroOutput {
+                pressed: Buttons::empty(),
+                sticks: None,
+                keyboard: Keyboard::default(),
+            })),
+            pending: Arc::new(Mutex::new(None)),
+        };
+        engine.reload(config, Buttons::empty());
+        engine
+    }
+
+    /// Slot a config watcher can drop a new config into; it is swapped in before the next frame.
+    pub fn pending_config(&self) -> PendingConfig {
+        self.pending.clone()
+    }
+
+    pub fn status(&self) -> Status {
+        let latched = |macros: &[Macro], states: &[MacroState]| {
+            macros
+                .iter()
+                .zip(states)
+                .filter(|(m, state)| m.mode == MacroMode::Toggle && state.active)
+                .fold(Buttons::empty(), |acc, (m, _)| acc | m.include)
+        };
+
+        let Some(profile) = self.profiles.get(self.active) else {
+            return Status {
+                profile: None,
+                layers: Vec::new(),
+                latched: Buttons::empty(),
+                turbo: Buttons::empty(),
+                orientation: self.orientation(),
+            };
+        };
+        let active_layers = profile
+            .layers
+            .iter()
+            .zip(&self.layers)
+            .filter(|(_, state)| state.active);
+        Status {
+            profile: Some(profile.name.clone()),
+            layers: active_layers
+                .clone()
+                .map(|(layer, _)| layer.name.clone())
+                .collect(),
+            latched: active_layers.fold(latched(&profile.macros, &self.macros), |acc, (l, s)| {
+                acc | latched(&l.macros, &s.macros)
+            }),
+            turbo: self
+                .turbo_latched
+                .iter()
+                .fold(Buttons::empty(), |acc, b| acc | *b),
+            orientation: self.orientation(),
+        }
+    }
+
+    pub fn orientation(&self) -> Quaternion {
+        self.fusion.orientation()
+    }
+
+    pub fn process(&mut self, mut state: ControllerStateInternal) -> ControllerStateInternal {
+        let pending = self.pending.lock().unwrap().take();
+        if let Some(config) = pending {
+            self.reload(config, state.button);
+        }
+
+        // The gyro offset keeps being estimated even while no profile uses it.
+        let now = Instant::now();
+        let gyro = self.gyro.calibrate(state.motion.gyro, now);
+        self.fusion.update(state.ts, gyro, state.motion.accel);
+
+        let Some(profile) = self.profiles.get(self.active) else {
+            return state;
+        };
+
+        // Filtering runs on the raw sticks, every later stage sees the cleaned position.
+        if !profile.filter.is_passthrough() {
+            let [left, right] = &mut self.filters;
+            state.l = left.apply(&profile.filter, state.l, now);
+            state.r = right.apply(&profile.filter, state.r, now);
+        }
+
+        // The digital L2/R2 bits follow the processed analog value so both always agree.
+        if !profile.l2.is_passthrough() {
+            let curve = self.curve(profile.l2.curve.as_deref());
+            state.l2_axis = profile.l2.apply(state.l2_axis, curve);
+            state.button.set(Buttons::L2, state.l2_axis > 0);
+        }
+        if !profile.r2.is_passthrough() {
+            let curve = self.curve(profile.r2.curve.as_deref());
+            state.r2_axis = profile.r2.apply(state.r2_axis, curve);
+            state.button.set(Buttons::R2, state.r2_axis > 0);
+        }
+
+        // Touchpad regions act like physical buttons for everything that follows.
+        state.button = self.touch.apply(
+            &profile.touch,
+            &state.touchpad,
+            self.touchpad_size,
+            state.button,
+            now,
+        );
+        if let Some(settings) = &profile.dpad.stick_to_dpad {
+            let stick = match settings.stick {
+                Stick::Left => &mut state.l,
+                Stick::Right => &mut state.r,
+            };
+            state.button |= settings.directions(*stick);
+            if !settings.keep_stick {
+                *stick = Axis2D { x: 0x80, y: 0x80 };
+            }
+        }
+
+        // Layers are resolved on the raw buttons, everything after that runs without the shifts.
+        let mut shift = Buttons::empty();
+        for (i, layer) in profile.layers.iter().enumerate() {
+            let active = is_layer_active(&profile.layers, i, state.button);
+            if active {
+                shift |= layer.shift;
+            }
+            self.layers[i].active = active;
+        }
+        let physical = state.button - shift;
+        set_triggers(&mut state, Buttons::empty(), shift);
+        for (layer, layer_state) in profile.layers.iter().zip(self.layers.iter_mut()) {
+            if !layer_state.active {
+                layer_state.macros.clear();
+            } else if layer_state.macros.is_empty() {
+                layer_state.macros = initial_states(&layer.macros, physical);
+            }
+        }
+
+        let mut frame = Frame {
+            buttons: physical,
+            sensitivity: &profile.sensitivity,
+            switch_to: None,
+            keyboard: Keyboard::default(),
+        };
+        run_macros(
+            &profile.macros,
+            &mut self.macros,
+            physical,
+            now,
+            &mut frame,
+            &self.macro_output,
+        );
+        for (layer, layer_state) in profile.layers.iter().zip(self.layers.iter_mut()) {
+            if !layer_state.active {
+                continue;
+            }
+            if let Some(name) = &layer.sensitivity {
+                frame.sensitivity = name;
+            }
+            run_macros(
+                &layer.macros,
+                &mut layer_state.macros,
+                physical,
+                now,
+                &mut frame,
+                &self.macro_output,
+            );
+        }
+        let Frame {
+            mut buttons,
+            sensitivity,
+            switch_to,
+            mut keyboard,
+        } = frame;
+
+        // Triggers a macro pressed or filtered out follow on the analog axis too.
+        set_triggers(&mut state, buttons - physical, physical - buttons);
+
+        state.l = profile.left_stick.apply(state.l);
+        state.r = profile.right_stick.apply(state.r);
+
+        let mut turbo = Vec::with_capacity(profile.turbo.len());
+        for (i, t) in profile.turbo.iter().enumerate() {
+            let held = physical & t.buttons;
+            let firing = match t.mode {
+                TurboMode::Hold => held & buttons,
+                TurboMode::Toggle => {
+                    self.turbo_latched[i] ^= held - self.previous;
+                    // The press that toggles is not sent, only the auto-fire.
+                    let before = buttons;
+                    buttons = (buttons - held) | self.turbo_latched[i];
+                    set_triggers(&mut state, self.turbo_latched[i], before - buttons);
+                    self.turbo_latched[i]
+                }
+            };
+            turbo.push(t.slot(firing));
+        }
+        state.turbo = turbo;
+        self.previous = physical;
+
+        if let Some(curve) = self.curve(Some(sensitivity)) {
+            state.l = Axis2D {
+                x: curve.get(state.l.x).y,
+                y: curve.get(state.l.y).y,
+            };
+            state.r = Axis2D {
+                x: curve.get(state.r.x).y,
+                y: curve.get(state.r.y).y,
+            };
+        }
+
+        // Gyro aiming goes on top of the curve, it has its own sensitivity.
+        if let Some(settings) = &profile.gyro {
+            state.r = self.gyro.apply(settings, gyro, physical, state.r, now);
+        }
+
+        for mapping in &profile.tilt {
+            mapping.apply(&mut state, &mut buttons);
+        }
+
+        {
+            let output = self.macro_output.lock().unwrap();
+            buttons |= output.pressed;
+            if let Some((left, right)) = output.sticks {
+                state.l = left;
+                state.r = right;
+            }
+            keyboard.merge(&output.keyboard);
+        }
+        state.keyboard = keyboard;
+
+        // Opposite directions are resolved on the final hat, whatever pressed them.
+        if let Some(policy) = profile.dpad.socd {
+            buttons = self.socd.apply(policy, buttons);
+        }
+        if let Some(stick) = profile.dpad.dpad_to_stick {
+            // The stick keeps its own input while the D-pad is released.
+            if buttons.intersects(dpad::HAT) {
+                let deflection = dpad::dpad_to_stick(buttons);
+                match stick {
+                    Stick::Left => state.l = deflection,
+                    Stick::Right => state.r = deflection,
+                }
+            }
+            buttons -= dpad::HAT;
+        }
+        if let Some(settings) = &profile.mouse {
+            let (mouse, rest) = self.trackpad.apply(
+                settings,
+                &state.touchpad,
+                self.touchpad_size.0,
+                buttons,
+                now,
+            );
+            state.mouse = mouse;
+            buttons = rest;
+        }
+        state.button = buttons;
+
+        if let Some(name) = switch_to
+            && let Some(index) = self.profiles.iter().position(|p| p.name == name)
+        {
+            self.switch(index, physical);
+        }
+
+        state
+    }
+
+    fn curve(&self, name: Option<&str>) -> Option<&SensitivityProfile> {
+        let name = name?;
+        self.sensitivities.iter().find(|s| s.name == name)
+    }
+
+    // Keeps the active profile across reloads as long as it still exists.
+    fn reload(&mut self, config: Config, physical: Buttons) {
+        let active = self.profiles.get(self.active).map(|p| p.name.clone());
+        self.profiles = config.profiles;
+        self.sensitivities = config.sensitivities;
+        if self.profiles.is_empty() {
+            self.active = 0;
+            self.macros.clear();
+            self.layers.clear();
+            return;
+        }
+        let index = active
+            .and_then(|name| self.profiles.iter().position(|p| p.name == name))
+            .unwrap_or(0);
+        self.switch(index, physical);
+    }
+
+    fn switch(&mut self, index: usize, physical: Buttons) {
+        self.active = index;
+        self.turbo_latched = vec![Buttons::empty(); self.profiles[index].turbo.len()];
+        self.previous = physical;
+        self.touch.reset();
+        self.filters = Default::default();
+        self.macros = initial_states(&self.profiles[index].macros, physical);
+        self.layers = self.profiles[index]
+            .layers
+            .iter()
+            .map(|_| LayerState::default())
+            .collect();
+    }
+}
+
+// Reports L2/R2 fully pressed on the analog axis when `pressed` has them, released when
+// `released` has them, so the host never sees the axis disagree with the digital bit.
+fn set_triggers(state: &mut ControllerStateInternal, pressed: Buttons, released: Buttons) {
+    for (button, axis) in [
+        (Buttons::L2, &mut state.l2_axis),
+        (Buttons::R2, &mut state.r2_axis),
+    ] {
+        if released.contains(button) {
+            *axis = 0;
+        } else if pressed.contains(button) {
+            *axis = 255;
+        }
+    }
+}
+
+// Buttons that are already held when a set of macros comes into effect must not fire them.
+fn initial_states(macros: &[Macro], physical: Buttons) -> Vec<MacroState> {
+    macros
+        .iter()
+        .map(|m| {
+            let held = m.is_triggered(physical);
+            MacroState {
+                held,
+                held_since: None,
+                active: held && m.mode == MacroMode::Momentary,
+            }
+        })
+        .collect()
+}
+
+// A held layer yields to any other held layer whose chord contains its own.
+fn is_layer_active(layers: &[Layer], index: usize, buttons: Buttons) -> bool {
+    let shift = layers[index].shift;
+    buttons.contains(shift)
+        && !layers
+            .iter()
+            .any(|l| l.shift != shift && l.shift.contains(shift) && buttons.contains(l.shift))
+}
+
+fn run_macros<'a>(
+    macros: &'a [Macro],
+    states: &mut [MacroState],
+    physical: Buttons,
+    now: Instant,
+    frame: &mut Frame<'a>,
+    output: &Arc<Mutex<MacroOutput>>,
+) {
+    for (m, macro_state) in macros.iter().zip(states.iter_mut()) {
+        let pressed = macro_state.update(m, physical, now);
+        if !macro_state.active {
+            // A pending long press holds its filter back, so a short press sends nothing.
+            if macro_state.held_since.is_some() {
+                frame.buttons -= m.filter;
+            }
+            continue;
+        }
+
+        frame.buttons = m.apply(frame.buttons);
+        if let Some(name) = &m.hold_sensitivity {
+            frame.sensitivity = name;
+        }
+        if let Some(key) = m.key {
+            frame.keyboard.press(key);
+        }
+        if pressed {
+            if let Some(actions) = &m.macro_list {
+                tokio::spawn(run_macro(actions.clone(), output.clone()));
+            }
+            if let Some(name) = &m.switch_profile {
+                frame.switch_to = Some(name);
+            }
+        }
+    }
+}
+
+async fn run_macro(actions: Vec<MacroAction>, output: Arc<Mutex<MacroOutput>>) {
+    for action in actions {
+        match action {
+            MacroAction::PressJoystick { left, right } => {
+                output.lock().unwrap().sticks = Some((left, right));
+            }
+            MacroAction::Press(buttons) => {
+                output.lock().unwrap().pressed |= buttons;
+            }
+            MacroAction::ReleaseJoystick => {
+                output.lock().unwrap().sticks = None;
+            }
+            MacroAction::Release(buttons) => {
+                output.lock().unwrap().pressed -= buttons;
+            }
+            MacroAction::Sleep(ms) => sleep(Duration::from_millis(ms)).await,
+            MacroAction::Key { usage, modifiers } => {
+                tap_key(&output, KeyPress { usage, modifiers }).await;
+            }
+            MacroAction::TypeText(text) => {
+                // The config only accepts text that has a key for every character.
+                for key in text.chars().filter_map(KeyPress::from_char) {
+                    tap_key(&output, key).await;
+                }
+            }
+        }
+    }
+}
+
+async fn tap_key(output: &Arc<Mutex<MacroOutput>>, key: KeyPress) {
+    output.lock().unwrap().keyboard.press(key);
+    sleep(KEY_TAP).await;
+    output.lock().unwrap().keyboard.release(key);
+    sleep(KEY_TAP).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::usb::ParsedInput;
+
+    fn engine(toml: &str) -> ProfileEngine {
+        let config = Config::from_toml(toml).unwrap_or_else(|e| panic!("{}", e));
+        ProfileEngine::new(config, InputDevice::DualSense)
+    }
+
+    fn pressed(buttons: Buttons) -> ControllerStateInternal {
+        let mut state = frame(0);
+        state.button = buttons;
+        state
+    }
+
+    // Idle DualSense frame with L2 pulled to `l2`; its digital bit is set while it is pulled.
+    fn frame(l2: u8) -> ControllerStateInternal {
+        let mut buf = [0u8; 64];
+        buf[0] = 0x01;
+        buf[1..5].fill(0x80);
+        buf[5] = l2;
+        buf[8] = 0x08;
+        if l2 > 0 {
+            buf[9] = 0x04;
+        }
+        ControllerStateInternal::from(ParsedInput::from_ps5_buf(&buf).unwrap())
+    }
+
+    #[test]
+    fn filtered_l2_stays_released_while_pulled() {
+        let mut engine = engine(
+            r#"
+            [[profile]]
+            name = "default"
+
+            [[profile.macro]]
+            include = ["L2"]
+            filter = ["L2"]
+            add = ["CROSS"]
+            "#,
+        );
+        let state = engine.process(frame(200));
+        assert_eq!(state.button, Buttons::CROSS);
+        assert_eq!(state.l2_axis, 0);
+    }
+
+    #[test]
+    fn macro_add_presses_the_trigger_axis() {
+        let mut engine = engine(
+            r#"
+            [[profile]]
+            name = "default"
+
+            [[profile.macro]]
+            include = ["L2"]
+            filter = ["L2"]
+            add = ["R2"]
+            "#,
+        );
+        let state = engine.process(frame(200));
+        assert_eq!((state.l2_axis, state.r2_axis), (0, 255));
+        assert_eq!(state.button, Buttons::R2);
+    }
+
+    #[test]
+    fn toggle_turbo_swallows_the_trigger_axis() {
+        let mut engine = engine(
+            r#"
+            [[profile]]
+            name = "default"
+
+            [[profile.turbo]]
+            buttons = ["L2"]
+            mode = "toggle"
+            "#,
+        );
+        // The first press latches the turbo on, which fires the trigger fully.
+        let state = engine.process(frame(200));
+        assert_eq!(state.l2_axis, 255);
+        // The second press turns it off again and is not sent either.
+        engine.process(frame(0));
+        let state = engine.process(frame(200));
+        assert_eq!(state.l2_axis, 0);
+        assert_eq!(state.button, Buttons::empty());
+    }
+
+    #[test]
+    fn l2_shift_is_not_sent_while_pulled() {
+        let mut engine = engine(
+            r#"
+            [[profile]]
+            name = "default"
+
+            [[profile.layer]]
+            name = "alt"
+            shift = ["L2"]
+            "#,
+        );
+        let state = engine.process(frame(200));
+        assert_eq!(state.button, Buttons::empty());
+        assert_eq!(state.l2_axis, 0);
+    }
+
+    #[test]
+    fn tilt_presses_the_trigger_it_raises() {
+        let mut engine = engine(
+            r#"
+            [[profile]]
+            name = "default"
+
+            [[profile.tilt]]
+            angle = "pitch"
+            target = "l2"
+            range = 45
+            "#,
+        );
+        // Tipped 30 degrees forward.
+        let mut state = frame(0);
+        let pitch = 30f32.to_radians();
+        state.motion.accel = [0.0, pitch.cos(), pitch.sin()];
+        let state = engine.process(state);
+        assert!(state.l2_axis > 0);
+        assert_eq!(state.button, Buttons::L2);
+    }
+
+    #[test]
+    fn tilt_leaves_a_filtered_trigger_released() {
+        let mut engine = engine(
+            r#"
+            [[profile]]
+            name = "default"
+
+            [[profile.tilt]]
+            angle = "pitch"
+            target = "l2"
+
+            [[profile.macro]]
+            include = ["L2"]
+            filter = ["L2"]
+            "#,
+        );
+        // Lying flat, the tilt does not raise the filtered trigger again.
+        let mut state = frame(200);
+        state.motion.accel = [0.0, 1.0, 0.0];
+        let state = engine.process(state);
+        assert_eq!(state.l2_axis, 0);
+        assert_eq!(state.button, Buttons::empty());
+    }
+
+    #[test]
+    fn toggle_latches_on_press_release_press() {
+        let mut engine = engine(
+            r#"
+            [[profile]]
+            name = "default"
+
+            [[profile.macro]]
+            include = ["SQUARE"]
+            add = ["CIRCLE"]
+            mode = "toggle"
+            "#,
+        );
+        let frames = [
+            Buttons::SQUARE,
+            Buttons::empty(),
+            Buttons::SQUARE,
+            Buttons::empty(),
+        ];
+        let mut sent = Vec::new();
+        let mut latched = Vec::new();
+        for buttons in frames {
+            sent.push(engine.process(pressed(buttons)).button);
+            latched.push(engine.status().latched);
+        }
+        assert_eq!(
+            sent,
+            [
+                Buttons::SQUARE | Buttons::CIRCLE,
+                Buttons::CIRCLE,
+                Buttons::SQUARE,
+                Buttons::empty()
+            ]
+        );
+        assert_eq!(
+            latched,
+            [
+                Buttons::SQUARE,
+                Buttons::SQUARE,
+                Buttons::empty(),
+                Buttons::empty()
+            ]
+        );
+    }
+
+    #[test]
+    fn long_press_activates_at_the_threshold() {
+        let config = Config::from_toml(
+            r#"
+            [[profile]]
+            name = "default"
+
+            [[profile.macro]]
+            include = ["CROSS"]
+            mode = "long_press"
+            long_press_ms = 500
+            "#,
+        )
+        .unwrap();
+        let m = &config.profiles[0].macros[0];
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut state = MacroState::default();
+
+        assert!(!state.update(m, Buttons::CROSS, at(0)));
+        assert!(!state.update(m, Buttons::CROSS, at(499)));
+        assert!(!state.active);
+        assert!(state.update(m, Buttons::CROSS, at(500)));
+        assert!(!state.update(m, Buttons::CROSS, at(800)));
+        assert!(state.active);
+        assert!(!state.update(m, Buttons::empty(), at(900)));
+        assert!(!state.active);
+
+        // A second press starts counting again.
+        assert!(!state.update(m, Buttons::CROSS, at(1000)));
+        assert!(!state.update(m, Buttons::CROSS, at(1499)));
+        assert!(state.update(m, Buttons::CROSS, at(1500)));
+    }
+
+    #[test]
+    fn pending_long_press_holds_back_its_filter() {
+        let mut engine = engine(
+            r#"
+            [[profile]]
+            name = "default"
+
+            [[profile.macro]]
+            include = ["OPTIONS"]
+            filter = ["OPTIONS"]
+            add = ["PS"]
+            mode = "long_press"
+            long_press_ms = 60000
+            "#,
+        );
+        let state = engine.process(pressed(Buttons::OPTIONS | Buttons::CROSS));
+        assert_eq!(state.button, Buttons::CROSS);
+        let state = engine.process(pressed(Buttons::empty()));
+        assert_eq!(state.button, Buttons::empty());
+    }
+
+    #[test]
+    fn switch_does_not_fire_macros_on_held_buttons() {
+        let mut engine = engine(
+            r#"
+            [[profile]]
+            name = "a"
+
+            [[profile.macro]]
+            include = ["L1"]
+            switch_profile = "b"
+
+            [[profile]]
+            name = "b"
+
+            [[profile.macro]]
+            include = ["L1"]
+            filter = ["L1"]
+            add = ["CROSS"]
+            mode = "toggle"
+            "#,
+        );
+        engine.process(pressed(Buttons::L1));
+        assert_eq!(engine.status().profile.as_deref(), Some("b"));
+
+        // The L1 that switched is still held, it must not latch b's toggle.
+        let state = engine.process(pressed(Buttons::L1));
+        assert_eq!(state.button, Buttons::L1);
+        assert_eq!(engine.status().latched, Buttons::empty());
+
+        engine.process(pressed(Buttons::empty()));
+        let state = engine.process(pressed(Buttons::L1));
+        assert_eq!(state.button, Buttons::CROSS);
+        assert_eq!(engine.status().latched, Buttons::L1);
+    }
+
+    #[test]
+    fn stick_to_dpad_centers_the_stick_unless_kept() {
+        for (keep_stick, x) in [(false, 128), (true, 255)] {
+            let mut engine = engine(&format!(
+                r#"
+                [[profile]]
+                name = "default"
+
+                [profile.dpad]
+                stick_to_dpad = {{ stick = "left", keep_stick = {} }}
+                "#,
+                keep_stick
+            ));
+            let mut state = frame(0);
+            state.l = Axis2D { x: 255, y: 128 };
+            let state = engine.process(state);
+            assert_eq!(state.button, Buttons::HAT_RIGHT);
+            assert_eq!((state.l.x, state.l.y), (x, 128));
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Instant;
+
+use crate::interfaces::internal::{Buttons, Mouse, MouseButtons, Touchpad};
+
+/// Drives the mouse collection from finger movement on the touchpad.
+#[derive(Debug, Clone)]
+pub struct MouseSettings {
+    /// Mouse counts per touchpad unit at slow finger speeds.
+    pub sensitivity: f32,
+    /// Extra gain per touchpad width per second of finger speed, 0 disables acceleration.
+    pub acceleration: f32,
+    /// Controller buttons that press the mouse buttons; they are not sent to the gamepad.
+    pub left: Buttons,
+    pub right: Buttons,
+    pub middle: Buttons,
+}
+
+/// Follows the first finger on the touchpad across frames.
+#[derive(Debug, Default)]
+pub struct Trackpad {
+    finger: Option<(u8, u16, u16)>,
+    last: Option<Instant>,
+    // Sub-count movement carried over so slow swipes are not lost to rounding.
+    remainder: (f32, f32),
+}
+
+impl Trackpad {
+    /// Returns this frame's mouse output and `buttons` without the ones used as mouse buttons.
+    pub fn apply(
+        &mut self,
+        settings: &MouseSettings,
+        touchpad: &Touchpad,
+        width: u16,
+        buttons: Buttons,
+        now: Instant,
+    ) -> (Mouse, Buttons) {
+        let dt = self.last.map(|last| now.duration_since(last).as_secs_f32());
+        self.last = Some(now);
+
+        let finger = touchpad.fingers[0].map(|p| (p.id, p.x, p.y));
+        let (mut dx, mut dy) = (0.0, 0.0);
+        // A new contact id means the finger was lifted, which must not move the pointer.
+        if let (Some((id, x, y)), Some((last_id, last_x, last_y))) = (finger, self.finger)
+            && id == last_id
+        {
+            dx = x as f32 - last_x as f32;
+            dy = y as f32 - last_y as f32;
+        } else {
+            self.remainder = (0.0, 0.0);
+        }
+        self.finger = finger;
+
+        let speed = match dt {
+            Some(dt) if dt > 0.0 => dx.hypot(dy) / width as f32 / dt,
+            _ => 0.0,
+        };
+        let gain = settings.sensitivity * (1.0 + settings.acceleration * speed);
+        let x = dx * gain + self.remainder.0;
+        let y = dy * gain + self.remainder.1;
+        self.remainder = (x.fract(), y.fract());
+
+        let mut mouse_buttons = MouseButtons::empty();
+        for (mapped, button) in [
+            (settings.left, MouseButtons::LEFT),
+            (settings.right, MouseButtons::RIGHT),
+            (settings.middle, MouseButtons::MIDDLE),
+        ] {
+            if buttons.intersects(mapped) {
+                mouse_buttons |= button;
+            }
+        }
+
+        let mouse = Mouse {
+            buttons: mouse_buttons,
+            dx: x.trunc() as i32,
+            dy: y.trunc() as i32,
+        };
+        (
+            mouse,
+            buttons - (settings.left | settings.right | settings.middle),
+        )
+    }
+}
@@ -0,0 +1,122 @@
+use serde::Deserialize;
+
+use crate::interfaces::internal::Axis2D;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadzoneShape {
+    /// Deadzone around the center, direction is kept intact.
+    #[default]
+    Radial,
+    /// Each axis gets its own deadzone, which snaps near-cardinal input onto the axes.
+    Axial,
+}
+
+/// Per-stick deadzone settings, all radii as a fraction of full deflection (0.0 - 1.0).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StickSettings {
+    /// Deflection below this is reported as centered.
+    pub deadzone: f32,
+    /// Deflection within this distance of the edge is reported as full.
+    pub outer_deadzone: f32,
+    /// Smallest deflection sent once the stick leaves the deadzone, to cancel in-game deadzones.
+    pub anti_deadzone: f32,
+    pub shape: DeadzoneShape,
+}
+
+impl StickSettings {
+    fn is_passthrough(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn apply(&self, stick: Axis2D) -> Axis2D {
+        if self.is_passthrough() {
+            return stick;
+        }
+
+        let (x, y) = to_unit(stick);
+        let (x, y) = match self.shape {
+            DeadzoneShape::Radial => {
+                let r = x.hypot(y);
+                if r == 0.0 {
+                    (0.0, 0.0)
+                } else {
+                    let scale = self.rescale(r) / r;
+                    (x * scale, y * scale)
+                }
+            }
+            DeadzoneShape::Axial => (
+                self.rescale(x.abs()).copysign(x),
+                self.rescale(y.abs()).copysign(y),
+            ),
+        };
+        from_unit(x, y)
+    }
+
+    // Maps a deflection magnitude through inner deadzone, outer saturation and anti-deadzone.
+    fn rescale(&self, magnitude: f32) -> f32 {
+        if magnitude <= self.deadzone {
+            return 0.0;
+        }
+        let live = (1.0 - self.deadzone - self.outer_deadzone).max(f32::EPSILON);
+        let scaled = ((magnitude - self.deadzone) / live).min(1.0);
+        self.anti_deadzone + (1.0 - self.anti_deadzone) * scaled
+    }
+}
+
+/// Converts a raw stick position into -1.0 - 1.0 around the 0x80 center.
+///
+/// The center sits one step closer to 255 than to 0, so each side has its own scale and both
+/// ends map to full deflection.
+pub fn to_unit(stick: Axis2D) -> (f32, f32) {
+    let axis = |v: u8| {
+        let offset = v as f32 - 128.0;
+        if offset < 0.0 {
+            offset / 128.0
+        } else {
+            offset / 127.0
+        }
+    };
+    (axis(stick.x), axis(stick.y))
+}
+
+pub fn from_unit(x: f32, y: f32) -> Axis2D {
+    let axis = |v: f32| {
+        let v = v.clamp(-1.0, 1.0);
+        let scale = if v < 0.0 { 128.0 } else { 127.0 };
+        (v * scale + 128.0).round() as u8
+    };
+    Axis2D {
+        x: axis(x),
+        y: axis(y),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ends_and_center_round_trip() {
+        for v in [0, 1, 127, 128, 129, 254, 255] {
+            let (x, y) = to_unit(Axis2D { x: v, y: v });
+            let stick = from_unit(x, y);
+            assert_eq!((stick.x, stick.y), (v, v));
+        }
+        assert_eq!(to_unit(Axis2D { x: 0, y: 255 }), (-1.0, 1.0));
+        assert_eq!(to_unit(Axis2D { x: 128, y: 128 }), (0.0, 0.0));
+    }
+
+    #[test]
+    fn deadzone_keeps_full_deflection() {
+        let settings = StickSettings {
+            deadzone: 0.05,
+            ..Default::default()
+        };
+        for v in [0, 128, 255] {
+            let stick = settings.apply(Axis2D { x: v, y: 128 });
+            assert_eq!((stick.x, stick.y), (v, 128));
+        }
+    }
+}
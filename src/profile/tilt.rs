@@ -0,0 +1,101 @@
+use serde::Deserialize;
+
+use crate::interfaces::internal::{Buttons, ControllerStateInternal};
+
+use super::stick::{from_unit, to_unit};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TiltAngle {
+    /// Tipping the front of the controller up or down.
+    Pitch,
+    /// Turning the controller like a steering wheel.
+    Roll,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TiltTarget {
+    LeftX,
+    LeftY,
+    /// Triggers only take the positive side of the tilt.
+    L2,
+    R2,
+}
+
+/// Maps one tilt angle, taken from the gravity vector, onto a stick axis or trigger.
+///
+/// The physical input keeps working: whichever of the two is deflected further wins.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TiltMapping {
+    pub angle: TiltAngle,
+    pub target: TiltTarget,
+    /// Angle in degrees that is reported as centered.
+    #[serde(default)]
+    pub neutral: f32,
+    /// Degrees away from `neutral` that give full deflection.
+    #[serde(default = "default_range")]
+    pub range: f32,
+    /// Degrees around `neutral` that are ignored.
+    #[serde(default)]
+    pub deadzone: f32,
+    #[serde(default)]
+    pub invert: bool,
+}
+
+fn default_range() -> f32 {
+    45.0
+}
+
+/// Pitch and roll in degrees, both 0 while the controller lies flat.
+pub fn angles([x, y, z]: [f32; 3]) -> (f32, f32) {
+    (z.atan2(y).to_degrees(), x.atan2(y).to_degrees())
+}
+
+impl TiltMapping {
+    /// Deflection in -1.0 - 1.0 for the given accelerometer sample.
+    pub fn value(&self, accel: [f32; 3]) -> f32 {
+        let (pitch, roll) = angles(accel);
+        let angle = match self.angle {
+            TiltAngle::Pitch => pitch,
+            TiltAngle::Roll => roll,
+        };
+        // Wrapped so a neutral near ±180 degrees does not jump across the seam.
+        let delta = (angle - self.neutral + 180.0).rem_euclid(360.0) - 180.0;
+        let live = (self.range - self.deadzone).max(f32::EPSILON);
+        let value = ((delta.abs() - self.deadzone).max(0.0) / live).min(1.0);
+        let value = value.copysign(delta);
+        if self.invert { -value } else { value }
+    }
+
+    /// Raising a trigger also presses its digital bit in `buttons`; a trigger the tilt does not
+    /// raise keeps whatever the earlier stages left of the bit.
+    pub fn apply(&self, state: &mut ControllerStateInternal, buttons: &mut Buttons) {
+        let value = self.value(state.motion.accel);
+        let tilt = from_unit(value, value);
+        let (x, y) = to_unit(state.l);
+        let (axis, button) = match self.target {
+            TiltTarget::LeftX if value.abs() > x.abs() => {
+                state.l.x = tilt.x;
+                return;
+            }
+            TiltTarget::LeftY if value.abs() > y.abs() => {
+                state.l.y = tilt.y;
+                return;
+            }
+            TiltTarget::LeftX | TiltTarget::LeftY => return,
+            TiltTarget::L2 => (&mut state.l2_axis, Buttons::L2),
+            TiltTarget::R2 => (&mut state.r2_axis, Buttons::R2),
+        };
+        let trigger = to_trigger(value);
+        if trigger > *axis {
+            *axis = trigger;
+            *buttons |= button;
+        }
+    }
+}
+
+fn to_trigger(value: f32) -> u8 {
+    (value.max(0.0) * 255.0).round() as u8
+}
@@ -0,0 +1,313 @@
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::interfaces::internal::{Buttons, TouchPoint, Touchpad};
+
+/// Contacts shorter than this, without clicking the pad, count as a tap.
+const TAP_TIME: Duration = Duration::from_millis(200);
+/// How long a tap holds the mapped buttons.
+const TAP_PULSE: Duration = Duration::from_millis(50);
+
+/// Area of the touchpad in fractions of its size, (0, 0) being the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl Rect {
+    fn contains(&self, (x, y): (f32, f32)) -> bool {
+        (self.left..self.right).contains(&x) && (self.top..self.bottom).contains(&y)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TouchRegion {
+    Whole,
+    LeftHalf,
+    RightHalf,
+    TopHalf,
+    BottomHalf,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl TouchRegion {
+    pub fn rect(self) -> Rect {
+        let (left, top, right, bottom) = match self {
+            TouchRegion::Whole => (0.0, 0.0, 1.0, 1.0),
+            TouchRegion::LeftHalf => (0.0, 0.0, 0.5, 1.0),
+            TouchRegion::RightHalf => (0.5, 0.0, 1.0, 1.0),
+            TouchRegion::TopHalf => (0.0, 0.0, 1.0, 0.5),
+            TouchRegion::BottomHalf => (0.0, 0.5, 1.0, 1.0),
+            TouchRegion::TopLeft => (0.0, 0.0, 0.5, 0.5),
+            TouchRegion::TopRight => (0.5, 0.0, 1.0, 0.5),
+            TouchRegion::BottomLeft => (0.0, 0.5, 0.5, 1.0),
+            TouchRegion::BottomRight => (0.5, 0.5, 1.0, 1.0),
+        };
+        Rect {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TouchActivation {
+    /// Held while a finger rests in the region.
+    #[default]
+    Touch,
+    /// A short touch in the region presses the buttons briefly.
+    Tap,
+    /// Held while the pad is clicked with a finger in the region; the click itself is not sent.
+    Click,
+}
+
+/// Presses `buttons` on touchpad input in `rect`.
+#[derive(Debug, Clone)]
+pub struct TouchMapping {
+    pub rect: Rect,
+    pub buttons: Buttons,
+    pub activation: TouchActivation,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Contact {
+    id: u8,
+    start: Instant,
+    position: (f32, f32),
+    clicked: bool,
+}
+
+/// Follows the fingers on the touchpad across frames to tell taps from longer touches.
+#[derive(Debug, Default)]
+pub struct TouchTracker {
+    contacts: [Option<Contact>; 2],
+    // When each tap mapping of the active profile releases its buttons again.
+    pulses: Vec<Option<Instant>>,
+}
+
+impl TouchTracker {
+    /// Applies `mappings` to the physical `buttons`. `size` is the touchpad resolution.
+    pub fn apply(
+        &mut self,
+        mappings: &[TouchMapping],
+        touchpad: &Touchpad,
+        size: (u16, u16),
+        buttons: Buttons,
+        now: Instant,
+    ) -> Buttons {
+        let clicked = buttons.contains(Buttons::TOUCHPAD);
+        let position = |p: &TouchPoint| (p.x as f32 / size.0 as f32, p.y as f32 / size.1 as f32);
+
+        // The controller gives every new contact a new id.
+        let mut taps = Vec::new();
+        for (slot, finger) in self.contacts.iter_mut().zip(&touchpad.fingers) {
+            if slot.map(|c| c.id) != finger.map(|p| p.id) {
+                if let Some(contact) = slot.take()
+                    && !contact.clicked
+                    && now.duration_since(contact.start) < TAP_TIME
+                {
+                    taps.push(contact.position);
+                }
+                *slot = finger.map(|p| Contact {
+                    id: p.id,
+                    start: now,
+                    position: position(&p),
+                    clicked: false,
+                });
+            }
+            if let Some(contact) = slot {
+                contact.clicked |= clicked;
+            }
+        }
+
+        self.pulses.resize(mappings.len(), None);
+        let mut mapped = Buttons::empty();
+        let mut click_used = false;
+        for (m, pulse) in mappings.iter().zip(self.pulses.iter_mut()) {
+            let touching = touchpad
+                .fingers
+                .iter()
+                .flatten()
+                .any(|p| m.rect.contains(position(p)));
+            let active = match m.activation {
+                TouchActivation::Touch => touching,
+                TouchActivation::Click => {
+                    click_used |= clicked && touching;
+                    clicked && touching
+                }
+                TouchActivation::Tap => {
+                    if taps.iter().any(|&p| m.rect.contains(p)) {
+                        *pulse = Some(now + TAP_PULSE);
+                    }
+                    pulse.is_some_and(|until| now < until)
+                }
+            };
+            if active {
+                mapped |= m.buttons;
+            }
+        }
+
+        if click_used {
+            (buttons - Buttons::TOUCHPAD) | mapped
+        } else {
+            buttons | mapped
+        }
+    }
+
+    /// Drops pending taps, e.g. when the mappings change with the profile.
+    pub fn reset(&mut self) {
+        self.pulses.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DS: (u16, u16) = (1920, 1080);
+    const DS4: (u16, u16) = (1920, 942);
+
+    fn mapping(region: TouchRegion, buttons: Buttons, activation: TouchActivation) -> TouchMapping {
+        TouchMapping {
+            rect: region.rect(),
+            buttons,
+            activation,
+        }
+    }
+
+    // One finger with the given id at (x, y), or none.
+    type Finger = Option<(u8, u16, u16)>;
+
+    fn touchpad(finger: Finger) -> Touchpad {
+        Touchpad {
+            fingers: [finger.map(|(id, x, y)| TouchPoint { id, x, y }), None],
+        }
+    }
+
+    // Feeds (ms since start, finger, physical buttons) frames, returns the output of each.
+    fn run(
+        mappings: &[TouchMapping],
+        size: (u16, u16),
+        frames: &[(u64, Finger, Buttons)],
+    ) -> Vec<Buttons> {
+        let start = Instant::now();
+        let mut tracker = TouchTracker::default();
+        frames
+            .iter()
+            .map(|&(ms, finger, buttons)| {
+                let now = start + Duration::from_millis(ms);
+                tracker.apply(mappings, &touchpad(finger), size, buttons, now)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn short_tap_pulses_the_buttons() {
+        let tap = [mapping(
+            TouchRegion::Whole,
+            Buttons::CROSS,
+            TouchActivation::Tap,
+        )];
+        let none = Buttons::empty();
+        let finger = Some((1, 960, 540));
+        let out = run(
+            &tap,
+            DS,
+            &[
+                (0, finger, none),
+                (199, finger, none),
+                (199, None, none),
+                (248, None, none),
+                (249, None, none),
+            ],
+        );
+        assert_eq!(out, [none, none, Buttons::CROSS, Buttons::CROSS, none]);
+    }
+
+    #[test]
+    fn long_touch_is_not_a_tap() {
+        let tap = [mapping(
+            TouchRegion::Whole,
+            Buttons::CROSS,
+            TouchActivation::Tap,
+        )];
+        let none = Buttons::empty();
+        let finger = Some((1, 960, 540));
+        let out = run(&tap, DS, &[(0, finger, none), (200, None, none)]);
+        assert_eq!(out, [none, none]);
+    }
+
+    #[test]
+    fn clicked_contact_is_not_a_tap() {
+        let tap = [mapping(
+            TouchRegion::Whole,
+            Buttons::CROSS,
+            TouchActivation::Tap,
+        )];
+        let none = Buttons::empty();
+        let finger = Some((1, 960, 540));
+        let out = run(
+            &tap,
+            DS,
+            &[
+                (0, finger, none),
+                (50, finger, Buttons::TOUCHPAD),
+                (100, finger, none),
+                (100, None, none),
+            ],
+        );
+        assert_eq!(out, [none, Buttons::TOUCHPAD, none, none]);
+    }
+
+    #[test]
+    fn click_only_strips_the_touchpad_in_its_region() {
+        let click = [mapping(
+            TouchRegion::LeftHalf,
+            Buttons::L3,
+            TouchActivation::Click,
+        )];
+        let out = run(
+            &click,
+            DS,
+            &[
+                (0, Some((1, 100, 540)), Buttons::TOUCHPAD),
+                (10, Some((1, 100, 540)), Buttons::empty()),
+                (20, Some((2, 1800, 540)), Buttons::TOUCHPAD),
+            ],
+        );
+        assert_eq!(out, [Buttons::L3, Buttons::empty(), Buttons::TOUCHPAD]);
+    }
+
+    #[test]
+    fn regions_scale_with_the_ds4_pad_height() {
+        let halves = [
+            mapping(
+                TouchRegion::TopHalf,
+                Buttons::TRIANGLE,
+                TouchActivation::Touch,
+            ),
+            mapping(
+                TouchRegion::BottomHalf,
+                Buttons::CROSS,
+                TouchActivation::Touch,
+            ),
+        ];
+        let at = |size, y| run(&halves, size, &[(0, Some((1, 960, y)), Buttons::empty())])[0];
+        assert_eq!(at(DS4, 470), Buttons::TRIANGLE);
+        assert_eq!(at(DS4, 471), Buttons::CROSS);
+        assert_eq!(at(DS4, 941), Buttons::CROSS);
+        // The same spot is still in the top half of the taller DualSense pad.
+        assert_eq!(at(DS, 471), Buttons::TRIANGLE);
+    }
+}
@@ -0,0 +1,79 @@
+use serde::Deserialize;
+
+use crate::interfaces::internal::SensitivityProfile;
+
+/// Per-trigger processing of the L2/R2 analog axes.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TriggerSettings {
+    /// Values at or below this are reported as released.
+    pub start: u8,
+    /// Values at or above this are reported as fully pressed.
+    pub end: u8,
+    /// Name of a sensitivity curve applied after the deadzones.
+    pub curve: Option<String>,
+    /// From this value (1 - 255) on the trigger is reported fully pressed, below it released.
+    pub hair_trigger: Option<u8>,
+}
+
+impl Default for TriggerSettings {
+    fn default() -> Self {
+        Self {
+            start: 0,
+            end: 255,
+            curve: None,
+            hair_trigger: None,
+        }
+    }
+}
+
+impl TriggerSettings {
+    pub fn is_passthrough(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn apply(&self, raw: u8, curve: Option<&SensitivityProfile>) -> u8 {
+        if let Some(threshold) = self.hair_trigger {
+            return if raw >= threshold { 255 } else { 0 };
+        }
+
+        let value = if raw <= self.start {
+            0
+        } else if raw >= self.end {
+            255
+        } else {
+            ((raw - self.start) as u32 * 255 / (self.end - self.start) as u32) as u8
+        };
+        match curve {
+            Some(curve) => curve.get(value).y,
+            None => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hair_trigger_fires_at_the_threshold() {
+        let settings = TriggerSettings {
+            hair_trigger: Some(40),
+            ..Default::default()
+        };
+        assert_eq!(settings.apply(0, None), 0);
+        assert_eq!(settings.apply(39, None), 0);
+        assert_eq!(settings.apply(40, None), 255);
+        assert_eq!(settings.apply(255, None), 255);
+    }
+
+    #[test]
+    fn lowest_hair_trigger_rests_released() {
+        let settings = TriggerSettings {
+            hair_trigger: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(settings.apply(0, None), 0);
+        assert_eq!(settings.apply(1, None), 255);
+    }
+}
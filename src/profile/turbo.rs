@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::interfaces::{
+    bluetooth::REPORT_INTERVAL,
+    internal::{Buttons, TurboSlot},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TurboMode {
+    /// Fires while the button is physically held.
+    #[default]
+    Hold,
+    /// A press starts firing, the next press stops it.
+    Toggle,
+}
+
+/// Auto-repeat for a set of buttons, timed in report loop ticks.
+#[derive(Debug, Clone)]
+pub struct Turbo {
+    pub buttons: Buttons,
+    pub press_ticks: u8,
+    pub release_ticks: u8,
+    pub mode: TurboMode,
+}
+
+impl Turbo {
+    /// Rounds the cadence to whole report ticks, at least one tick for each phase.
+    pub fn from_millis(buttons: Buttons, press_ms: u64, release_ms: u64, mode: TurboMode) -> Self {
+        Self {
+            buttons,
+            press_ticks: to_ticks(press_ms),
+            release_ticks: to_ticks(release_ms),
+            mode,
+        }
+    }
+
+    pub fn slot(&self, buttons: Buttons) -> TurboSlot {
+        TurboSlot {
+            buttons,
+            press_ticks: self.press_ticks,
+            release_ticks: self.release_ticks,
+        }
+    }
+}
+
+fn to_ticks(ms: u64) -> u8 {
+    let ticks = Duration::from_millis(ms).as_secs_f64() / REPORT_INTERVAL.as_secs_f64();
+    ticks.round().clamp(1.0, u8::MAX as f64) as u8
+}